@@ -38,11 +38,23 @@ mkdir ../docs/diablo
 cp ../res/african_head/model.obj ../docs/african_head/
 cp ../res/african_head/normals.tga ../docs/african_head/
 cp ../res/african_head/texture.tga ../docs/african_head/
+# morph targets of the head, optional, list.txt tells the page what to fetch
+mkdir ../docs/african_head/morphs
+touch ../docs/african_head/morphs/list.txt
+if [ -d ../res/african_head/morphs ]; then
+    for f in ../res/african_head/morphs/*.obj; do
+        [ -f "$f" ] || continue
+        cp "$f" ../docs/african_head/morphs/
+        basename "$f" >> ../docs/african_head/morphs/list.txt
+    done
+fi
 cp ../res/diablo/model.obj ../docs/diablo/
 cp ../res/diablo/normals.tga ../docs/diablo/
 cp ../res/diablo/texture.tga ../docs/diablo/
 
 cp ../static/index.css ../docs/
+# image for the background selector, optional
+[ -f ../res/background.tga ] && cp ../res/background.tga ../docs/
 
 for f in `ls -p | grep -v /`; do
     cp $f ../docs/
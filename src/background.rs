@@ -0,0 +1,81 @@
+use crate::{
+    env::EnvView,
+    gbuffer::GBuffer,
+    hdr::HdrImage,
+    la::{interpolatev, Vec3f},
+    tga::{Color, Image},
+};
+
+// whatever is drawn behind the model, pixels without geometry are at max depth.
+// colors are linear
+pub enum Background {
+    Solid(Vec3f),
+    Gradient { top: Vec3f, bottom: Vec3f },
+    // stretched over the whole screen
+    Image(Image),
+    // skybox, the lighting environment rotated with the camera
+    Environment,
+}
+
+impl Background {
+    // skybox, solid:r,g,b, gradient:r,g,b:r,g,b (top then bottom) or image:file.tga
+    pub fn parse(s: &str) -> Background {
+        let color = |c: &str| Vec3f::parse(c).unwrap_or_else(|| panic!("bad color {}", c));
+        let items: Vec<&str> = s.splitn(3, ':').collect();
+        match items[..] {
+            ["skybox"] => Background::Environment,
+            ["solid", c] => Background::Solid(color(c)),
+            ["gradient", top, bottom] => Background::Gradient {
+                top: color(top),
+                bottom: color(bottom),
+            },
+            ["image", file] => Background::Image(Image::from_file(file.to_string())),
+            _ => panic!("bad background {}", s),
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Background::Solid(_) => "Solid",
+            Background::Gradient { .. } => "Gradient",
+            Background::Image(_) => "Image",
+            Background::Environment => "Skybox",
+        }
+    }
+
+    // the skybox is part of the lit scene and goes through the post chain
+    pub fn draw_sky(&self, out: &mut HdrImage, gbuffer: &GBuffer, env: &EnvView) {
+        if let Background::Environment = self {
+            env.draw_background(out, gbuffer);
+        }
+    }
+
+    // everything else goes over the finished image so the colors come out as given
+    pub fn draw(&self, out: &mut Image, gbuffer: &GBuffer) {
+        if let Background::Environment = self {
+            return;
+        }
+        for y in 0..out.height {
+            // image rows go from the top, screen rows from the bottom
+            let v = 1.0 - y as f32 / (out.height - 1) as f32;
+            for x in 0..out.width {
+                if !gbuffer.is_empty(x, y) {
+                    continue;
+                }
+                let c = match self {
+                    Background::Solid(c) => Color::from_linear(c),
+                    Background::Gradient { top, bottom } => {
+                        Color::from_linear(&interpolatev(top, bottom, v))
+                    }
+                    Background::Image(img) => img.pixel_at(
+                        x * img.width / out.width,
+                        (v * (img.height - 1) as f32).round() as i32,
+                    ),
+                    Background::Environment => unreachable!(),
+                };
+                out.set_pixel(x, y, c);
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::la::{eye, get_look_at, persp, Matrix, Vec3f};
+
+// persp sees the points with |x| and |y| under 0.2 of their distance from the eye
+const FOV_SLOPE: f32 = 0.2;
+
+// orbit camera. persp keeps the eye at eye(5.0) in view space, the look at matrix
+// is moved along the view axis to put it distance away from the target instead
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub target: Vec3f,
+    // from the target towards the eye, any length
+    pub dir: Vec3f,
+    pub distance: f32,
+    // depth range from the eye mapped onto the z buffer
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    // the fixed camera everything used to be rendered with, for models in [-1, 1]
+    pub fn new() -> Self {
+        Camera {
+            target: Vec3f(0.0, 0.0, 0.0),
+            dir: Vec3f(0.5, 0.5, 1.0),
+            distance: eye(5.0).2,
+            near: 4.5,
+            far: 6.75,
+        }
+    }
+
+    // same direction, target and distances picked for the sphere to fill the view
+    pub fn frame(&self, (center, radius): (Vec3f, f32)) -> Camera {
+        let radius = radius.max(1e-4);
+        let distance = radius * (1.0 + FOV_SLOPE * FOV_SLOPE).sqrt() / FOV_SLOPE;
+        Camera {
+            target: center,
+            dir: self.dir,
+            distance,
+            // a bit of slack so the nearest and farthest points aren't clipped
+            near: (distance - radius * 1.05).max(distance * 0.01),
+            far: distance + radius * 1.05,
+        }
+    }
+
+    // how much bigger the framed view is than the one new() is made for, distances
+    // picked for the head (fog, ssao, outlines, lights) are multiplied by it
+    pub fn scale(&self) -> f32 {
+        self.distance / eye(5.0).2
+    }
+
+    // a point given around the origin for new(), at the same spot in this view
+    pub fn place(&self, p: &Vec3f) -> Vec3f {
+        self.target.add(&p.mulf(self.scale()))
+    }
+
+    pub fn look_at_m(&self) -> Matrix<4, 4> {
+        let origin = self
+            .target
+            .add(&self.dir.normalize().mulf(self.distance - eye(5.0).2));
+        get_look_at(&origin.add(&self.dir), &origin)
+    }
+
+    // z buffer value of a persp depth, 255 at near and 0 at far
+    pub fn depth(&self, z: f32) -> f32 {
+        let plane = |d: f32| persp(5.0, &Vec3f(0.0, 0.0, eye(5.0).2 - d)).2;
+        let (near, far) = (plane(self.near), plane(self.far));
+        (z - far) / (near - far) * 255.0
+    }
+}
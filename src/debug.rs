@@ -0,0 +1,123 @@
+use crate::{
+    gbuffer::GBuffer,
+    hdr::{HdrImage, ToneMap},
+    la::{interpolatev, Rng, Vec3f},
+    tga::{Color, Image},
+};
+
+// what the shader writes instead of the lit color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DebugView {
+    Off,
+    WorldNormals,
+    ViewNormals,
+    UvChecker,
+    UvGradient,
+    Tangents,
+    TriangleIds,
+    // fragment shader invocations per pixel, hidden ones included
+    Overdraw,
+}
+
+impl DebugView {
+    #[cfg(not(feature = "local"))]
+    pub fn next(&self) -> Self {
+        match self {
+            DebugView::Off => DebugView::WorldNormals,
+            DebugView::WorldNormals => DebugView::ViewNormals,
+            DebugView::ViewNormals => DebugView::UvChecker,
+            DebugView::UvChecker => DebugView::UvGradient,
+            DebugView::UvGradient => DebugView::Tangents,
+            DebugView::Tangents => DebugView::TriangleIds,
+            DebugView::TriangleIds => DebugView::Overdraw,
+            DebugView::Overdraw => DebugView::Off,
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugView::Off => "Off",
+            DebugView::WorldNormals => "World normals",
+            DebugView::ViewNormals => "View normals",
+            DebugView::UvChecker => "UV checker",
+            DebugView::UvGradient => "UV gradient",
+            DebugView::Tangents => "Tangents",
+            DebugView::TriangleIds => "Triangle IDs",
+            DebugView::Overdraw => "Overdraw",
+        }
+    }
+
+    // off, world-normals, view-normals, uv-checker, uv-gradient, tangents,
+    // triangle-ids or overdraw
+    pub fn parse(s: &str) -> DebugView {
+        match s.to_lowercase().as_str() {
+            "off" => DebugView::Off,
+            "world-normals" => DebugView::WorldNormals,
+            "view-normals" => DebugView::ViewNormals,
+            "uv-checker" => DebugView::UvChecker,
+            "uv-gradient" => DebugView::UvGradient,
+            "tangents" => DebugView::Tangents,
+            "triangle-ids" => DebugView::TriangleIds,
+            "overdraw" => DebugView::Overdraw,
+            _ => panic!("bad debug view {}", s),
+        }
+    }
+}
+
+// linear color that ends up as exactly c on the screen, debug colors are display values
+pub fn display(c: &Vec3f) -> Vec3f {
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color(channel(c.2), channel(c.1), channel(c.0)).to_linear()
+}
+
+// unit vector components from [-1, 1] to [0, 1]
+pub fn vector(v: &Vec3f) -> Vec3f {
+    display(&v.normalize().mulf(0.5).add(&Vec3f(0.5, 0.5, 0.5)))
+}
+
+pub fn uv_checker(u: f32, v: f32) -> Vec3f {
+    let cell = ((u * 8.0).floor() + (v * 8.0).floor()) as i32;
+    if cell.rem_euclid(2) == 0 {
+        display(&Vec3f(0.9, 0.9, 0.9))
+    } else {
+        display(&Vec3f(0.2, 0.2, 0.2))
+    }
+}
+
+pub fn uv_gradient(u: f32, v: f32) -> Vec3f {
+    display(&Vec3f(u, v, 0.0))
+}
+
+pub fn triangle_id(face: usize) -> Vec3f {
+    // spread neighbouring ids before seeding, xorshift starts out correlated
+    let mut rng = Rng::new((face as u32 + 1).wrapping_mul(0x9e37_79b9));
+    display(&Vec3f(rng.next_f32(), rng.next_f32(), rng.next_f32()))
+}
+
+fn heat(count: u32) -> Vec3f {
+    let palette = [
+        Vec3f(0.0, 0.0, 0.0),
+        Vec3f(0.0, 0.0, 1.0),
+        Vec3f(0.0, 1.0, 0.0),
+        Vec3f(1.0, 1.0, 0.0),
+        Vec3f(1.0, 0.0, 0.0),
+        Vec3f(1.0, 1.0, 1.0),
+    ];
+    // every color step is two more invocations
+    let t = count as f32 / 2.0;
+    let i = (t.floor() as usize).min(palette.len() - 2);
+    interpolatev(&palette[i], &palette[i + 1], (t - i as f32).min(1.0))
+}
+
+// debug views skip lighting passes and tone mapping
+pub fn finish(mut out: HdrImage, gbuffer: &GBuffer, view: DebugView) -> Image {
+    if view == DebugView::Overdraw {
+        for y in 0..out.height {
+            for x in 0..out.width {
+                out.set_pixel(x, y, display(&heat(gbuffer.overdraw_at(x, y))));
+            }
+        }
+    }
+    out.to_image(0.0, ToneMap::Clamp)
+}
@@ -0,0 +1,133 @@
+use crate::{
+    gbuffer::GBuffer,
+    hdr::HdrImage,
+    la::{eye, Vec3f},
+    tga::{Color, Image},
+};
+
+const MAX_COC: f32 = 16.0;
+const SAMPLES: usize = 48;
+const TILE: i32 = 16;
+
+// thin lens camera parameters, the pinhole persp keeps using the same eye
+#[derive(Debug, Clone, Copy)]
+pub struct Lens {
+    // distance from the eye that is in focus
+    pub focus_distance: f32,
+    // blur of things at infinity as a fraction of the image height, zero is a pinhole
+    pub aperture: f32,
+}
+
+impl Lens {
+    pub fn new() -> Self {
+        Lens {
+            focus_distance: eye(5.0).length(),
+            aperture: 0.0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.aperture > 0.0
+    }
+
+    // signed circle of confusion radius in pixels, negative in front of the focus plane
+    fn coc(&self, gbuffer: &GBuffer, x: i32, y: i32) -> f32 {
+        let distance = if gbuffer.is_empty(x, y) {
+            f32::INFINITY
+        } else {
+            gbuffer.position_at(x, y).sub(&eye(5.0)).length()
+        };
+        let c = self.aperture * (1.0 - self.focus_distance / distance) * gbuffer.height as f32;
+        c.clamp(-MAX_COC, MAX_COC)
+    }
+
+    // gather blur, every pixel collects the neighbours whose circle of confusion covers it
+    pub fn apply(&self, out: &mut HdrImage, gbuffer: &GBuffer) {
+        let (width, height) = (out.width, out.height);
+        let mut coc = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                coc.push(self.coc(gbuffer, x, y));
+            }
+        }
+
+        // largest blur around every tile, decides how wide to look
+        let (tiles_x, tiles_y) = ((width + TILE - 1) / TILE, (height + TILE - 1) / TILE);
+        let mut tile_max = vec![0f32; (tiles_x * tiles_y) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let t = &mut tile_max[(x / TILE + y / TILE * tiles_x) as usize];
+                *t = t.max(coc[(x + y * width) as usize].abs());
+            }
+        }
+
+        // golden angle spiral, covers the unit disk evenly
+        let kernel: Vec<(f32, f32, f32)> = (0..SAMPLES)
+            .map(|i| {
+                let r = ((i as f32 + 0.5) / SAMPLES as f32).sqrt();
+                let a = i as f32 * 2.399_963;
+                (r * a.cos(), r * a.sin(), r)
+            })
+            .collect();
+
+        let src = out.data.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let (tx, ty) = (x / TILE, y / TILE);
+                let mut radius = 0f32;
+                for yy in (ty - 1).max(0)..=(ty + 1).min(tiles_y - 1) {
+                    for xx in (tx - 1).max(0)..=(tx + 1).min(tiles_x - 1) {
+                        radius = radius.max(tile_max[(xx + yy * tiles_x) as usize]);
+                    }
+                }
+                if radius < 0.5 {
+                    continue;
+                }
+
+                let i = (x + y * width) as usize;
+                let c0 = coc[i];
+                let mut total = src[i];
+                let mut weights = 1.0;
+                for (kx, ky, kr) in kernel.iter() {
+                    let sx = x + (kx * radius).round() as i32;
+                    let sy = y + (ky * radius).round() as i32;
+                    if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                        continue;
+                    }
+                    let s = (sx + sy * width) as usize;
+                    // things behind can't blur over sharper things in front of them
+                    let cs = if coc[s] > c0 {
+                        coc[s].abs().min(c0.abs())
+                    } else {
+                        coc[s].abs()
+                    };
+                    let w = (cs - kr * radius + 1.0).clamp(0.0, 1.0);
+                    total = total.add(&src[s].mulf(w));
+                    weights += w;
+                }
+                out.set_pixel(x, y, total.mulf(1.0 / weights));
+            }
+        }
+    }
+
+    // tints the depth view, green is in focus, red is in front and blue behind it
+    pub fn focus_check(&self, z_buffer: &mut Image, gbuffer: &GBuffer) {
+        for y in 0..z_buffer.height {
+            for x in 0..z_buffer.width {
+                if gbuffer.is_empty(x, y) {
+                    continue;
+                }
+                let c = self.coc(gbuffer, x, y);
+                let tint = if c.abs() < 1.0 {
+                    Vec3f(0.3, 1.0, 0.3)
+                } else if c < 0.0 {
+                    Vec3f(1.0, 0.4, 0.4)
+                } else {
+                    Vec3f(0.4, 0.4, 1.0)
+                };
+                let d = z_buffer.pixel_at(x, y).to_linear();
+                z_buffer.set_pixel(x, y, Color::from_linear(&d.mulv(&tint)));
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::{f32::consts::PI, fs, path::Path};
+
+use crate::{
+    gbuffer::GBuffer,
+    hdr::HdrImage,
+    la::{interpolatev, view_ray, Matrix, MatrixI, Vec3f},
+    tga::Image,
+};
+
+// prefiltered levels are spread evenly over roughness 0..1,
+// the first one is the radiance map itself
+const LEVELS: usize = 5;
+const CONVOLUTION_SIZE: (i32, i32) = (64, 32);
+const LEVEL_SIZE: (i32, i32) = (32, 16);
+
+// environment map stored as an equirectangular image, row 0 looks up
+pub struct Environment {
+    pub radiance: HdrImage,
+    sh: [Vec3f; 9],
+    levels: Vec<HdrImage>,
+}
+
+fn to_uv(d: &Vec3f) -> (f32, f32) {
+    let u = 0.5 + d.0.atan2(-d.2) / (2.0 * PI);
+    let v = d.1.clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+fn to_dir(u: f32, v: f32) -> Vec3f {
+    let phi = (u - 0.5) * 2.0 * PI;
+    let theta = v * PI;
+    Vec3f(
+        theta.sin() * phi.sin(),
+        theta.cos(),
+        -theta.sin() * phi.cos(),
+    )
+}
+
+// texel centers of an equirect image with their solid angles
+fn texels(img: &HdrImage) -> Vec<(Vec3f, Vec3f, f32)> {
+    let mut res = Vec::with_capacity((img.width * img.height) as usize);
+    for y in 0..img.height {
+        let v = (y as f32 + 0.5) / img.height as f32;
+        let d_omega = (2.0 * PI / img.width as f32) * (PI / img.height as f32) * (v * PI).sin();
+        for x in 0..img.width {
+            let u = (x as f32 + 0.5) / img.width as f32;
+            res.push((to_dir(u, v), img.pixel_at(x, y), d_omega));
+        }
+    }
+    res
+}
+
+fn sh_basis(d: &Vec3f) -> [f32; 9] {
+    let Vec3f(x, y, z) = *d;
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+fn ggx(n_h: f32, roughness: f32) -> f32 {
+    let a2 = (roughness * roughness).powi(2);
+    a2 / (PI * (n_h * n_h * (a2 - 1.0) + 1.0).powi(2))
+}
+
+impl Environment {
+    pub fn from_equirect(radiance: HdrImage) -> Self {
+        let small = radiance.downsample(CONVOLUTION_SIZE.0, CONVOLUTION_SIZE.1);
+        let texels = texels(&small);
+
+        // diffuse irradiance projected onto the first 3 bands of spherical harmonics
+        let mut sh = [Vec3f::zeroed(); 9];
+        for (d, l, d_omega) in texels.iter() {
+            for (c, b) in sh.iter_mut().zip(sh_basis(d).iter()) {
+                *c = c.add(&l.mulf(b * d_omega));
+            }
+        }
+
+        // specular levels, brute force ggx convolution with n = v = r
+        let mut levels = Vec::with_capacity(LEVELS - 1);
+        for level in 1..LEVELS {
+            let roughness = level as f32 / (LEVELS - 1) as f32;
+            let mut img = HdrImage::new(LEVEL_SIZE.0, LEVEL_SIZE.1);
+            for y in 0..img.height {
+                for x in 0..img.width {
+                    let r = to_dir(
+                        (x as f32 + 0.5) / img.width as f32,
+                        (y as f32 + 0.5) / img.height as f32,
+                    );
+                    let mut total = Vec3f::zeroed();
+                    let mut weights = 0.0;
+                    for (l, c, d_omega) in texels.iter() {
+                        let n_l = r.mul(l);
+                        if n_l <= 0.0 {
+                            continue;
+                        }
+                        let h = r.add(l).normalize();
+                        let w = ggx(r.mul(&h), roughness) * n_l * d_omega;
+                        total = total.add(&c.mulf(w));
+                        weights += w;
+                    }
+                    img.set_pixel(x, y, total.mulf(1.0 / weights.max(1e-6)));
+                }
+            }
+            levels.push(img);
+        }
+
+        Environment {
+            radiance,
+            sh,
+            levels,
+        }
+    }
+
+    // a radiance .hdr equirect or a directory with px, nx, py, ny, pz and nz tga faces
+    pub fn load(path: &str) -> Self {
+        if Path::new(path).is_dir() {
+            let face = |f: &str| {
+                let file = Path::new(path).join(format!("{}.tga", f));
+                HdrImage::from_image(&Image::from_file(file.to_string_lossy().to_string()))
+            };
+            return Environment::from_cube_map(&[
+                face("px"),
+                face("nx"),
+                face("py"),
+                face("ny"),
+                face("pz"),
+                face("nz"),
+            ]);
+        }
+        let bytes = fs::read(path).unwrap_or_else(|e| panic!("can't read {}: {}", path, e));
+        let radiance = HdrImage::from_radiance(bytes)
+            .unwrap_or_else(|e| panic!("bad radiance file {}: {}", path, e));
+        Environment::from_equirect(radiance)
+    }
+
+    // faces go in +x, -x, +y, -y, +z, -z order, all of them square
+    pub fn from_cube_map(faces: &[HdrImage; 6]) -> Self {
+        let size = faces[0].width;
+        let mut radiance = HdrImage::new(size * 4, size * 2);
+        for y in 0..radiance.height {
+            for x in 0..radiance.width {
+                let d = to_dir(
+                    (x as f32 + 0.5) / radiance.width as f32,
+                    (y as f32 + 0.5) / radiance.height as f32,
+                );
+                let (ax, ay, az) = (d.0.abs(), d.1.abs(), d.2.abs());
+                let (face, sc, tc, ma) = if ax >= ay && ax >= az {
+                    if d.0 > 0.0 {
+                        (0, -d.2, -d.1, ax)
+                    } else {
+                        (1, d.2, -d.1, ax)
+                    }
+                } else if ay >= az {
+                    if d.1 > 0.0 {
+                        (2, d.0, d.2, ay)
+                    } else {
+                        (3, d.0, -d.2, ay)
+                    }
+                } else if d.2 > 0.0 {
+                    (4, d.0, -d.1, az)
+                } else {
+                    (5, -d.0, -d.1, az)
+                };
+                let f = &faces[face];
+                let fx = (((sc / ma + 1.0) / 2.0) * f.width as f32) as i32;
+                let fy = (((tc / ma + 1.0) / 2.0) * f.height as f32) as i32;
+                radiance.set_pixel(x, y, f.pixel_at(fx.min(f.width - 1), fy.min(f.height - 1)));
+            }
+        }
+        Environment::from_equirect(radiance)
+    }
+
+    // procedural sky with a sun, used when there is no environment image around
+    pub fn sky() -> Self {
+        let sun = Vec3f(1.0, 0.6, 0.5).normalize();
+        let mut radiance = HdrImage::new(128, 64);
+        for y in 0..radiance.height {
+            for x in 0..radiance.width {
+                let d = to_dir(
+                    (x as f32 + 0.5) / radiance.width as f32,
+                    (y as f32 + 0.5) / radiance.height as f32,
+                );
+                let horizon = Vec3f(0.9, 0.95, 1.0);
+                let mut c = if d.1 > 0.0 {
+                    interpolatev(&horizon, &Vec3f(0.25, 0.45, 0.85), d.1.sqrt())
+                } else {
+                    interpolatev(&horizon, &Vec3f(0.3, 0.25, 0.2), (-d.1 * 8.0).min(1.0))
+                };
+                let s = d.mul(&sun).max(0.0);
+                c = c.add(&Vec3f(1.0, 0.9, 0.7).mulf(2.0 * s.powi(64)));
+                if s > 0.9986 {
+                    c = c.add(&Vec3f(30.0, 28.0, 25.0));
+                }
+                radiance.set_pixel(x, y, c);
+            }
+        }
+        Environment::from_equirect(radiance)
+    }
+
+    pub fn radiance(&self, d: &Vec3f) -> Vec3f {
+        let (u, v) = to_uv(d);
+        self.radiance.sample(u, v)
+    }
+
+    // cosine weighted integral of the radiance around the normal
+    pub fn irradiance(&self, n: &Vec3f) -> Vec3f {
+        let a = [
+            PI,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            2.0 * PI / 3.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+            PI / 4.0,
+        ];
+        let mut res = Vec3f::zeroed();
+        for ((c, b), a) in self.sh.iter().zip(sh_basis(n).iter()).zip(a.iter()) {
+            res = res.add(&c.mulf(a * b));
+        }
+        Vec3f(res.0.max(0.0), res.1.max(0.0), res.2.max(0.0))
+    }
+
+    // prefiltered radiance around the reflected direction
+    pub fn specular(&self, r: &Vec3f, roughness: f32) -> Vec3f {
+        let (u, v) = to_uv(r);
+        let level = |i: usize| {
+            if i == 0 {
+                self.radiance.sample(u, v)
+            } else {
+                self.levels[i - 1].sample(u, v)
+            }
+        };
+        let f = roughness.clamp(0.0, 1.0) * (LEVELS - 1) as f32;
+        let i = (f.floor() as usize).min(LEVELS - 2);
+        interpolatev(&level(i), &level(i + 1), f - i as f32)
+    }
+
+    // view of the environment from the camera described by the look at matrix
+    pub fn look_at(&self, m: &Matrix<4, 4>) -> EnvView<'_> {
+        EnvView {
+            env: self,
+            to_world: m.transpose(),
+        }
+    }
+}
+
+// same lookups as Environment but with directions given in view space
+#[derive(Clone, Copy)]
+pub struct EnvView<'a> {
+    pub env: &'a Environment,
+    to_world: Matrix<4, 4>,
+}
+
+impl EnvView<'_> {
+    fn world(&self, d: &Vec3f) -> Vec3f {
+        let r: Vec3f = self.to_world.mul(&d.embed::<4>(0.0)).into();
+        r.normalize()
+    }
+
+    pub fn irradiance(&self, n: &Vec3f) -> Vec3f {
+        self.env.irradiance(&self.world(n))
+    }
+
+    pub fn specular(&self, r: &Vec3f, roughness: f32) -> Vec3f {
+        self.env.specular(&self.world(r), roughness)
+    }
+
+    // fills pixels not covered by geometry
+    pub fn draw_background(&self, out: &mut HdrImage, gbuffer: &GBuffer) {
+        for y in 0..out.height {
+            for x in 0..out.width {
+                if !gbuffer.is_empty(x, y) {
+                    continue;
+                }
+                let ray = view_ray(
+                    5.0,
+                    x as f32 * 2.0 / (out.width - 1) as f32 - 1.0,
+                    y as f32 * 2.0 / (out.height - 1) as f32 - 1.0,
+                );
+                out.set_pixel(x, y, self.env.radiance(&self.world(&ray)));
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    camera::Camera,
+    gbuffer::GBuffer,
+    hdr::HdrImage,
+    la::{eye, interpolatev, look_at, Matrix, MatrixI, Vec3f},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FogMode {
+    Off,
+    Linear,
+    Exp,
+    Exp2,
+}
+
+impl FogMode {
+    #[cfg(not(feature = "local"))]
+    pub fn next(&self) -> Self {
+        match self {
+            FogMode::Off => FogMode::Linear,
+            FogMode::Linear => FogMode::Exp,
+            FogMode::Exp => FogMode::Exp2,
+            FogMode::Exp2 => FogMode::Off,
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            FogMode::Off => "Off",
+            FogMode::Linear => "Linear",
+            FogMode::Exp => "Exp",
+            FogMode::Exp2 => "Exp2",
+        }
+    }
+
+    // off, linear, exp or exp2
+    pub fn parse(s: &str) -> FogMode {
+        match s.to_lowercase().as_str() {
+            "off" => FogMode::Off,
+            "linear" => FogMode::Linear,
+            "exp" => FogMode::Exp,
+            "exp2" => FogMode::Exp2,
+            _ => panic!("bad fog mode {}", s),
+        }
+    }
+}
+
+// distances are measured from the eye, heights are world space y
+#[derive(Debug, Clone, Copy)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: Vec3f,
+    pub density: f32,
+    // linear fog range
+    pub start: f32,
+    pub end: f32,
+    // exponential height fog, zero density turns it off
+    pub height_density: f32,
+    pub height_falloff: f32,
+    pub height_base: f32,
+    // fog each fragment while shading instead of a pass over the depth buffer
+    pub per_fragment: bool,
+}
+
+impl Fog {
+    pub fn new() -> Self {
+        Fog {
+            mode: FogMode::Off,
+            color: Vec3f(0.6, 0.65, 0.7),
+            density: 0.1,
+            start: 4.5,
+            end: 7.0,
+            height_density: 0.0,
+            height_falloff: 4.0,
+            height_base: -0.5,
+            per_fragment: false,
+        }
+    }
+
+    // the distances and heights set for the head stretched to the framed view
+    pub fn framed(&self, camera: &Camera) -> Fog {
+        let s = camera.scale();
+        Fog {
+            density: self.density / s,
+            start: self.start * s,
+            end: self.end * s,
+            height_density: self.height_density / s,
+            height_falloff: self.height_falloff / s,
+            height_base: camera.place(&Vec3f(0.0, self.height_base, 0.0)).1,
+            ..*self
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.mode != FogMode::Off || self.height_density > 0.0
+    }
+
+    // fraction of the fog color for a point at the given distance,
+    // eye_y and y are world heights of the eye and the point
+    pub fn amount(&self, distance: f32, eye_y: f32, y: f32) -> f32 {
+        let distance_t = match self.mode {
+            FogMode::Off => 1.0,
+            FogMode::Linear => ((self.end - distance) / (self.end - self.start)).clamp(0.0, 1.0),
+            FogMode::Exp => (-self.density * distance).exp(),
+            FogMode::Exp2 => (-(self.density * distance).powi(2)).exp(),
+        };
+
+        // density integrated along the ray through exponentially thinning fog
+        let height_t = if self.height_density > 0.0 {
+            let dy = y - eye_y;
+            let k = self.height_falloff * dy;
+            let ray = if k.abs() > 1e-4 {
+                (1.0 - (-k).exp()) / k
+            } else {
+                1.0
+            };
+            let at_eye = (-self.height_falloff * (eye_y - self.height_base)).exp();
+            (-self.height_density * distance * at_eye * ray).exp()
+        } else {
+            1.0
+        };
+
+        1.0 - distance_t * height_t
+    }
+
+    // position is in view space, to_world is the inverse of the look at matrix
+    pub fn apply(&self, c: &Vec3f, position: &Vec3f, to_world: &Matrix<4, 4>) -> Vec3f {
+        let e = eye(5.0);
+        let distance = position.sub(&e).length();
+        let eye_y = look_at(to_world, &e).1;
+        let y = look_at(to_world, position).1;
+        interpolatev(c, &self.color, self.amount(distance, eye_y, y))
+    }
+
+    // depth based pass over everything the geometry covered
+    pub fn apply_pass(&self, out: &mut HdrImage, gbuffer: &GBuffer, lookat_m: &Matrix<4, 4>) {
+        let to_world = lookat_m.inverse();
+        for y in 0..out.height {
+            for x in 0..out.width {
+                if gbuffer.is_empty(x, y) {
+                    continue;
+                }
+                let c = self.apply(&out.pixel_at(x, y), &gbuffer.position_at(x, y), &to_world);
+                out.set_pixel(x, y, c);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framed_fog_matches_at_scaled_distances() {
+        let camera = Camera::new().frame((Vec3f(0.0, 10.0, 0.0), 10.0));
+        let s = camera.scale();
+        for mode in [FogMode::Linear, FogMode::Exp, FogMode::Exp2] {
+            let fog = Fog {
+                mode,
+                height_density: 0.5,
+                ..Fog::new()
+            };
+            let framed = fog.framed(&camera);
+            let a = fog.amount(5.5, 0.3, -0.2);
+            let b = framed.amount(5.5 * s, 10.0 + 0.3 * s, 10.0 - 0.2 * s);
+            assert!((a - b).abs() < 1e-4, "{:?} {} {}", mode, a, b);
+        }
+    }
+}
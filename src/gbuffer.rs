@@ -0,0 +1,76 @@
+use crate::la::Vec3f;
+
+// per-pixel geometry of the last rendered frame in view space,
+// used by the screen space passes
+pub struct GBuffer {
+    pub width: i32,
+    pub height: i32,
+    pub depth: Vec<f32>,
+    pub positions: Vec<Vec3f>,
+    pub normals: Vec<Vec3f>,
+    // fragment shader invocations, including the ones that failed the depth test
+    pub overdraw: Vec<u32>,
+}
+
+impl GBuffer {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        GBuffer {
+            width,
+            height,
+            depth: vec![f32::NEG_INFINITY; size],
+            positions: vec![Vec3f::zeroed(); size],
+            normals: vec![Vec3f::zeroed(); size],
+            overdraw: vec![0; size],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((x + y * self.width) as usize)
+        }
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, position: Vec3f, normal: Vec3f) {
+        if let Some(i) = self.index(x, y) {
+            self.depth[i] = position.2;
+            self.positions[i] = position;
+            self.normals[i] = normal;
+        }
+    }
+
+    pub fn count(&mut self, x: i32, y: i32) {
+        if let Some(i) = self.index(x, y) {
+            self.overdraw[i] += 1;
+        }
+    }
+
+    pub fn is_empty(&self, x: i32, y: i32) -> bool {
+        self.depth_at(x, y) == f32::NEG_INFINITY
+    }
+
+    // view space z, the bigger the closer to the camera
+    pub fn depth_at(&self, x: i32, y: i32) -> f32 {
+        self.index(x, y)
+            .map(|i| self.depth[i])
+            .unwrap_or(f32::NEG_INFINITY)
+    }
+
+    pub fn position_at(&self, x: i32, y: i32) -> Vec3f {
+        self.index(x, y)
+            .map(|i| self.positions[i])
+            .unwrap_or_else(Vec3f::zeroed)
+    }
+
+    pub fn normal_at(&self, x: i32, y: i32) -> Vec3f {
+        self.index(x, y)
+            .map(|i| self.normals[i])
+            .unwrap_or_else(Vec3f::zeroed)
+    }
+
+    pub fn overdraw_at(&self, x: i32, y: i32) -> u32 {
+        self.index(x, y).map(|i| self.overdraw[i]).unwrap_or(0)
+    }
+}
@@ -0,0 +1,1082 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::{
+    json::Json,
+    la::{Matrix, MatrixI, Quat, Vec3f},
+    material::Material,
+    model::{Group, Model, Wavefront},
+    morph::{Morph, MorphClip, MorphTarget},
+    png,
+    scene::{Instance, Scene, Transform},
+    skin::{Channel, Clip, Joint, JointPose, Keys, Skeleton, Skin},
+    tga::{Color, Image},
+};
+
+// gltf 2.0 scenes from .gltf with external or embedded buffers and from .glb.
+// triangle meshes, the node hierarchy, metallic-roughness materials with png
+// textures, skins, morph targets and animations. a node becomes a group instance
+// with an instance per primitive under it
+pub fn load(path: &str) -> Scene {
+    let bytes = fs::read(path).unwrap_or_else(|e| panic!("can't read {}: {}", path, e));
+    from_bytes(path, &bytes)
+}
+
+// path names the file in errors, relative uris are next to it
+fn from_bytes(path: &str, bytes: &[u8]) -> Scene {
+    let dir = Path::new(path).parent().unwrap_or_else(|| Path::new("."));
+    let (json, bin) = if bytes.starts_with(b"glTF") {
+        glb(path, bytes)
+    } else {
+        let json = Json::parse(&String::from_utf8_lossy(bytes))
+            .unwrap_or_else(|e| panic!("{}: bad json: {}", path, e));
+        (json, None)
+    };
+    let read = |uri: &str| -> Vec<u8> {
+        match uri.strip_prefix("data:") {
+            Some(data) => match data.find(',') {
+                Some(comma) => base64(&data[comma + 1..]),
+                None => panic!("{}: bad data uri", path),
+            },
+            None => {
+                let file = dir.join(uri.replace("%20", " "));
+                fs::read(&file).unwrap_or_else(|e| panic!("can't read {}: {}", file.display(), e))
+            }
+        }
+    };
+    let buffers = json
+        .get("buffers")
+        .items()
+        .iter()
+        .map(|b| match b.get("uri").as_str() {
+            Some(uri) => read(uri),
+            None => bin
+                .clone()
+                .unwrap_or_else(|| panic!("{}: buffer without uri outside of a glb", path)),
+        })
+        .collect();
+    let mut doc = Document {
+        path,
+        json: &json,
+        buffers,
+        images: Vec::new(),
+    };
+    doc.images = json
+        .get("images")
+        .items()
+        .iter()
+        .enumerate()
+        .map(|(i, img)| {
+            let bytes = match img.get("uri").as_str() {
+                Some(uri) => read(uri),
+                None => doc.view(img.get("bufferView").as_usize()?).0.to_vec(),
+            };
+            if bytes.starts_with(b"\x89PNG") {
+                let img = png::decode(&bytes)
+                    .unwrap_or_else(|e| panic!("{}: bad png image {}: {}", path, i, e));
+                Some(img)
+            } else {
+                eprintln!(
+                    "{}: only png textures are supported, skipped image {}",
+                    path, i
+                );
+                None
+            }
+        })
+        .collect();
+    doc.scene()
+}
+
+// 12 byte header, then a json chunk and an optional binary one
+fn glb(path: &str, bytes: &[u8]) -> (Json, Option<Vec<u8>>) {
+    let at = |p: usize| u32::from_le_bytes([bytes[p], bytes[p + 1], bytes[p + 2], bytes[p + 3]]);
+    let (mut json, mut bin) = (None, None);
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let len = at(pos) as usize;
+        let body = bytes.get(pos + 8..pos + 8 + len).unwrap_or_else(|| {
+            panic!(
+                "{}: glb chunk of {} bytes at {} past the end",
+                path, len, pos
+            )
+        });
+        match &bytes[pos + 4..pos + 8] {
+            b"JSON" => {
+                let chunk = Json::parse(&String::from_utf8_lossy(body))
+                    .unwrap_or_else(|e| panic!("{}: bad json chunk: {}", path, e));
+                json = Some(chunk);
+            }
+            b"BIN\0" => bin = Some(body.to_vec()),
+            _ => {}
+        }
+        pos += 8 + len;
+    }
+    let json = json.unwrap_or_else(|| panic!("{}: glb without a json chunk", path));
+    (json, bin)
+}
+
+fn base64(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => continue,
+        };
+        acc = (acc << 6 | v as u32) & 0xffffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+// column major like every gltf matrix
+fn matrix(v: &[f32]) -> Matrix<4, 4> {
+    let mut m: Matrix<4, 4> = Matrix::zeroed();
+    for (i, x) in v.iter().take(16).enumerate() {
+        m[i % 4][i / 4] = *x;
+    }
+    m
+}
+
+fn vec3(v: &[f32]) -> Vec3f {
+    Vec3f(v[0], v[1], v[2])
+}
+
+// translation, rotation and scale of a matrix without shear
+fn decompose(m: &Matrix<4, 4>) -> JointPose {
+    let column = |c: usize| Vec3f(m[0][c], m[1][c], m[2][c]);
+    let scale = Vec3f(column(0).length(), column(1).length(), column(2).length());
+    let mut r = *m;
+    for row in r.iter_mut().take(3) {
+        row[0] /= scale.0;
+        row[1] /= scale.1;
+        row[2] /= scale.2;
+    }
+    JointPose {
+        translation: column(3),
+        rotation: Quat::from_matrix(&r),
+        scale,
+    }
+}
+
+// euler angles of a rotation for Transform, which turns around x, then y, then z
+fn euler(q: &Quat) -> Vec3f {
+    let r = q.matrix();
+    if r[2][0].abs() < 0.9999 {
+        Vec3f(
+            r[2][1].atan2(r[2][2]),
+            (-r[2][0]).asin(),
+            r[1][0].atan2(r[0][0]),
+        )
+    } else {
+        // gimbal lock, all of the turn around z
+        Vec3f(
+            0.0,
+            -r[2][0].signum() * std::f32::consts::FRAC_PI_2,
+            (-r[0][1]).atan2(r[1][1]),
+        )
+    }
+}
+
+// one channel of an image as a gray one, what material maps read
+fn gray(img: &Image, channel: impl Fn(&Color) -> u8) -> Image {
+    Image {
+        width: img.width,
+        height: img.height,
+        data: img
+            .data
+            .iter()
+            .map(|c| {
+                let v = channel(c);
+                Color(v, v, v)
+            })
+            .collect(),
+    }
+}
+
+// up to four joints and their weights per vertex
+type Weights = (Vec<[usize; 4]>, Vec<[f32; 4]>);
+
+// attributes of a primitive's vertices, indexed like its positions
+struct Primitive {
+    model: Model,
+    joints: Option<Weights>,
+}
+
+struct Document<'a> {
+    // for errors
+    path: &'a str,
+    json: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    images: Vec<Option<Image>>,
+}
+
+impl Document<'_> {
+    fn view(&self, i: usize) -> (&[u8], Option<usize>) {
+        let v = self.json.get("bufferViews").at(i);
+        let b = v.get("buffer").as_usize();
+        let buffer = match b.and_then(|b| self.buffers.get(b)) {
+            Some(buffer) => buffer,
+            None => panic!("{}: buffer view {} without a buffer", self.path, i),
+        };
+        let offset = v.get("byteOffset").as_usize().unwrap_or(0);
+        let len = v
+            .get("byteLength")
+            .as_usize()
+            .unwrap_or_else(|| panic!("{}: buffer view {} without a length", self.path, i));
+        let data = buffer.get(offset..offset + len).unwrap_or_else(|| {
+            panic!(
+                "{}: buffer view {} ends at {}, past the {} bytes of its buffer",
+                self.path,
+                i,
+                offset + len,
+                buffer.len()
+            )
+        });
+        (data, v.get("byteStride").as_usize())
+    }
+
+    // elements as floats and how many make one, normalized integers end up in
+    // [0, 1] or [-1, 1]. accessors without a view are all zeros
+    fn accessor(&self, i: usize) -> (Vec<f32>, usize) {
+        let a = self.json.get("accessors").at(i);
+        let count = a
+            .get("count")
+            .as_usize()
+            .unwrap_or_else(|| panic!("{}: accessor {} without a count", self.path, i));
+        let components = match a.get("type").as_str().unwrap_or("SCALAR") {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" | "MAT2" => 4,
+            "MAT3" => 9,
+            "MAT4" => 16,
+            t => panic!("{}: bad accessor type {}", self.path, t),
+        };
+        let kind = a.get("componentType").as_usize().unwrap_or(5126);
+        let size = match kind {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            k => panic!("{}: bad component type {}", self.path, k),
+        };
+        let normalized = a.get("normalized").as_bool().unwrap_or(false);
+        let view = a.get("bufferView").as_usize().map(|v| self.view(v));
+        let offset = a.get("byteOffset").as_usize().unwrap_or(0);
+        let stride = view
+            .and_then(|(_, stride)| stride)
+            .unwrap_or(size * components);
+        // checked before allocating, the last element has to end inside the view.
+        // accessors without one can't be bigger than all the buffers together
+        let end = match count {
+            0 => Some(0),
+            _ => (count - 1)
+                .checked_mul(stride)
+                .and_then(|e| e.checked_add(offset + size * components)),
+        };
+        match view {
+            Some((data, _)) if end.is_none_or(|end| end > data.len()) => panic!(
+                "{}: accessor {} reads past the {} bytes of its buffer view",
+                self.path,
+                i,
+                data.len()
+            ),
+            None if end
+                .is_none_or(|end| end > self.buffers.iter().map(Vec::len).sum::<usize>()) =>
+            {
+                panic!(
+                    "{}: accessor {} without a buffer view has {} elements, more than the buffers hold",
+                    self.path, i, count
+                )
+            }
+            _ => {}
+        }
+        let mut out = vec![0.0; count * components];
+        if let Some((data, _)) = view {
+            for (e, element) in out.chunks_mut(components).enumerate() {
+                for (c, x) in element.iter_mut().enumerate() {
+                    let p = offset + e * stride + c * size;
+                    let b = data.get(p..p + size).unwrap_or_else(|| {
+                        panic!(
+                            "{}: accessor {} reads past the {} bytes of its buffer view",
+                            self.path,
+                            i,
+                            data.len()
+                        )
+                    });
+                    let (v, max) = match kind {
+                        5120 => (b[0] as i8 as f32, 127.0),
+                        5121 => (b[0] as f32, 255.0),
+                        5122 => (i16::from_le_bytes([b[0], b[1]]) as f32, 32767.0),
+                        5123 => (u16::from_le_bytes([b[0], b[1]]) as f32, 65535.0),
+                        5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32, 1.0),
+                        _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]), 1.0),
+                    };
+                    *x = if normalized { (v / max).max(-1.0) } else { v };
+                }
+            }
+        }
+        (out, components)
+    }
+
+    // an accessor read for what, which needs count elements (any number without
+    // one) of one of the component counts
+    fn attribute(
+        &self,
+        i: usize,
+        what: &str,
+        count: Option<usize>,
+        components: &[usize],
+    ) -> (Vec<f32>, usize) {
+        let (v, c) = self.accessor(i);
+        let elements = v.len() / c;
+        if !components.contains(&c) || count.is_some_and(|count| count != elements) {
+            panic!(
+                "{}: accessor {} has {} elements of {} for {}, which needs {} of {:?}",
+                self.path,
+                i,
+                elements,
+                c,
+                what,
+                count.map_or("any number".to_string(), |n| n.to_string()),
+                components
+            );
+        }
+        (v, c)
+    }
+
+    fn texture(&self, info: &Json) -> Option<&Image> {
+        let texture = self.json.get("textures").at(info.get("index").as_usize()?);
+        self.images.get(texture.get("source").as_usize()?)?.as_ref()
+    }
+
+    // base color texture and normal map go to the model, the rest to its material
+    fn material(&self, m: &Json) -> (Material, Option<Image>, Option<Image>) {
+        let pbr = m.get("pbrMetallicRoughness");
+        let factor = pbr.get("baseColorFactor").floats();
+        let mr = self.texture(pbr.get("metallicRoughnessTexture"));
+        let material = Material {
+            base_color: if factor.len() >= 3 {
+                vec3(&factor)
+            } else {
+                Vec3f(1.0, 1.0, 1.0)
+            },
+            metallic: pbr.get("metallicFactor").as_f32().unwrap_or(1.0),
+            roughness: pbr.get("roughnessFactor").as_f32().unwrap_or(1.0),
+            base_color_map: None,
+            // metalness is in blue and roughness in green, colors are stored bgr
+            metallic_map: mr.map(|img| gray(img, |c| c.0)),
+            roughness_map: mr.map(|img| gray(img, |c| c.1)),
+            ao_map: self
+                .texture(m.get("occlusionTexture"))
+                .map(|img| gray(img, |c| c.2)),
+        };
+        (
+            material,
+            self.texture(pbr.get("baseColorTexture")).cloned(),
+            self.texture(m.get("normalTexture")).cloned(),
+        )
+    }
+
+    // None for points and lines
+    fn primitive(&self, p: &Json) -> Option<Primitive> {
+        if p.get("mode").as_usize().unwrap_or(4) != 4 {
+            eprintln!(
+                "{}: only triangle primitives are supported, skipped one",
+                self.path
+            );
+            return None;
+        }
+        let attributes = p.get("attributes");
+        let (positions, _) = self.attribute(
+            attributes.get("POSITION").as_usize()?,
+            "POSITION",
+            None,
+            &[3],
+        );
+        let vertices: Vec<Vec3f> = positions.chunks(3).map(vec3).collect();
+        let n = vertices.len();
+        // every other attribute has an element per position
+        let floats = |name: &str, components: &[usize]| {
+            attributes
+                .get(name)
+                .as_usize()
+                .map(|a| self.attribute(a, name, Some(n), components))
+        };
+        let normals: Vec<Vec3f> =
+            floats("NORMAL", &[3]).map_or(Vec::new(), |(v, _)| v.chunks(3).map(vec3).collect());
+        // gltf images start at the top
+        let uv = floats("TEXCOORD_0", &[2]).map_or(vec![[0.0, 0.0]; n], |(v, _)| {
+            v.chunks(2).map(|t| [t[0], 1.0 - t[1]]).collect()
+        });
+        let indices: Vec<i32> = match p.get("indices").as_usize() {
+            Some(a) => self
+                .attribute(a, "indices", None, &[1])
+                .0
+                .iter()
+                .map(|i| *i as i32)
+                .collect(),
+            None => (0..n as i32).collect(),
+        };
+        if let Some(i) = indices.iter().find(|i| **i as usize >= n) {
+            panic!(
+                "{}: vertex index {} past the {} vertices of a primitive",
+                self.path, i, n
+            );
+        }
+        let faces: Vec<([i32; 3], [i32; 3])> = indices
+            .chunks_exact(3)
+            .map(|f| ([f[0], f[1], f[2]], [f[0], f[1], f[2]]))
+            .collect();
+        let normal_indices = if normals.is_empty() {
+            Vec::new()
+        } else {
+            faces.iter().map(|(v, _)| *v).collect()
+        };
+        // linear already, alpha is dropped
+        let colors = floats("COLOR_0", &[3, 4]).map_or(Vec::new(), |(v, components)| {
+            v.chunks(components).map(vec3).collect()
+        });
+        // flat normals are generated for primitives without them
+        let groups = if normals.is_empty() {
+            vec![Group {
+                smoothing: Some(0),
+                ..Group::new(0)
+            }]
+        } else {
+            Vec::new()
+        };
+        let wavefront = Wavefront {
+            colors,
+            groups,
+            ..Wavefront::new(vertices, faces, normals, normal_indices, uv)
+        };
+
+        let (material, texture, normal_map) = match p.get("material").as_usize() {
+            Some(m) => self.material(self.json.get("materials").at(m)),
+            None => (Material::new(), None, None),
+        };
+        let mut model = Model::untextured(wavefront);
+        if let Some(texture) = texture {
+            model.texture = texture;
+        }
+        if let Some(normal_map) = normal_map {
+            model.normal_map = normal_map;
+        }
+        model.tangent_normals = true;
+        model.material = material;
+
+        let sources: Vec<usize> = model
+            .mesh
+            .sources
+            .iter()
+            .map(|(v, _, _)| *v as usize)
+            .collect();
+        let targets: Vec<MorphTarget> = p
+            .get("targets")
+            .items()
+            .iter()
+            .map(|t| {
+                let deltas = |name: &str| -> Vec<Vec3f> {
+                    match t.get(name).as_usize() {
+                        Some(a) => {
+                            let what = format!("morph target {}", name);
+                            let (v, _) = self.attribute(a, &what, Some(n), &[3]);
+                            sources.iter().map(|i| vec3(&v[i * 3..])).collect()
+                        }
+                        None => vec![Vec3f::zeroed(); sources.len()],
+                    }
+                };
+                MorphTarget {
+                    positions: deltas("POSITION"),
+                    normals: deltas("NORMAL"),
+                }
+            })
+            .collect();
+        if !targets.is_empty() {
+            model.morph = Some(Morph {
+                targets,
+                clips: Vec::new(),
+            });
+        }
+
+        let joints =
+            floats("JOINTS_0", &[4])
+                .zip(floats("WEIGHTS_0", &[4]))
+                .map(|((j, _), (w, _))| {
+                    sources
+                        .iter()
+                        .map(|i| {
+                            let joints = [0, 1, 2, 3].map(|k| j[i * 4 + k] as usize);
+                            let weights = [0, 1, 2, 3].map(|k| w[i * 4 + k]);
+                            let sum: f32 = weights.iter().sum();
+                            let weights = if sum > 0.0 {
+                                weights.map(|w| w / sum)
+                            } else {
+                                [1.0, 0.0, 0.0, 0.0]
+                            };
+                            (joints, weights)
+                        })
+                        .unzip()
+                });
+        Some(Primitive { model, joints })
+    }
+
+    fn pose(&self, node: &Json) -> JointPose {
+        let m = node.get("matrix").floats();
+        if m.len() == 16 {
+            return decompose(&matrix(&m));
+        }
+        let t = node.get("translation").floats();
+        let r = node.get("rotation").floats();
+        let s = node.get("scale").floats();
+        JointPose {
+            translation: if t.len() == 3 {
+                vec3(&t)
+            } else {
+                Vec3f::zeroed()
+            },
+            rotation: if r.len() == 4 {
+                Quat(r[0], r[1], r[2], r[3]).normalize()
+            } else {
+                Quat::identity()
+            },
+            scale: if s.len() == 3 {
+                vec3(&s)
+            } else {
+                Vec3f(1.0, 1.0, 1.0)
+            },
+        }
+    }
+
+    // keyframe times and values of an animation channel, cubic splines keep
+    // their values and drop the tangents, step is interpolated linearly
+    fn keys(&self, animation: &Json, channel: &Json) -> (Vec<f32>, Vec<f32>, usize) {
+        let sampler = channel
+            .get("sampler")
+            .as_usize()
+            .map(|s| animation.get("samplers").at(s))
+            .filter(|s| !s.is_null())
+            .unwrap_or_else(|| panic!("{}: animation channel without a sampler", self.path));
+        let accessor = |name: &str| {
+            let a = sampler
+                .get(name)
+                .as_usize()
+                .unwrap_or_else(|| panic!("{}: animation sampler without {}", self.path, name));
+            self.accessor(a)
+        };
+        let (times, _) = accessor("input");
+        let (values, components) = accessor("output");
+        let cubic = sampler.get("interpolation").as_str() == Some("CUBICSPLINE");
+        // an element per key, three (in tangent, value, out tangent) for splines,
+        // times the number of morph targets for weights
+        let keys = times.len() * if cubic { 3 } else { 1 };
+        if keys == 0 || (values.len() / components) % keys != 0 {
+            panic!(
+                "{}: animation sampler with {} keyframes and {} values",
+                self.path,
+                times.len(),
+                values.len()
+            );
+        }
+        let per_key = values.len() / times.len().max(1);
+        let values = if cubic {
+            values
+                .chunks(per_key)
+                .flat_map(|k| k[per_key / 3..per_key * 2 / 3].to_vec())
+                .collect()
+        } else {
+            values
+        };
+        (times, values, components)
+    }
+
+    fn duration(&self, animation: &Json) -> f32 {
+        animation
+            .get("channels")
+            .items()
+            .iter()
+            .map(|c| self.keys(animation, c).0.last().copied().unwrap_or(0.0))
+            .fold(0.0, f32::max)
+    }
+
+    // joints sorted so parents come first, a clip per animation
+    fn skin(
+        &self,
+        skin: &Json,
+        parents: &[Option<usize>],
+        world: &[Matrix<4, 4>],
+        mesh_node: usize,
+        joints: Vec<[usize; 4]>,
+        weights: Vec<[f32; 4]>,
+    ) -> Skin {
+        let nodes: Vec<usize> = skin
+            .get("joints")
+            .items()
+            .iter()
+            .filter_map(|j| j.as_usize())
+            .collect();
+        let depth = |mut n: usize| {
+            let mut d = 0;
+            while let Some(p) = parents[n] {
+                n = p;
+                d += 1;
+            }
+            d
+        };
+        let mut order: Vec<usize> = (0..nodes.len()).collect();
+        order.sort_by_key(|j| depth(nodes[*j]));
+        let mut remap = vec![0; nodes.len()];
+        for (i, j) in order.iter().enumerate() {
+            remap[*j] = i;
+        }
+        let index = |node: usize| nodes.iter().position(|n| *n == node).map(|j| remap[j]);
+
+        let inverse_binds = skin.get("inverseBindMatrices").as_usize().map(|a| {
+            self.attribute(a, "inverseBindMatrices", Some(nodes.len()), &[16])
+                .0
+        });
+        let node_json = self.json.get("nodes");
+        let mut root = None;
+        let skeleton_joints = order
+            .iter()
+            .map(|j| {
+                let node = nodes[*j];
+                let mut parent = parents[node];
+                while let Some(p) = parent {
+                    if index(p).is_some() {
+                        break;
+                    }
+                    parent = parents[p];
+                }
+                let parent = parent.and_then(index);
+                if parent.is_none() && root.is_none() {
+                    // where the joints hang from relative to the skinned mesh
+                    let hang = parents[node].map_or(JointPose::new().matrix(), |p| world[p]);
+                    // a mesh hidden with a zero scale has nothing to hang them from
+                    root = world[mesh_node].try_inverse().map(|m| m.mul(&hang));
+                }
+                Joint {
+                    parent,
+                    inverse_bind: inverse_binds
+                        .as_ref()
+                        .map_or(JointPose::new().matrix(), |m| matrix(&m[j * 16..])),
+                    rest: self.pose(node_json.at(node)),
+                }
+            })
+            .collect();
+
+        let clips = self
+            .json
+            .get("animations")
+            .items()
+            .iter()
+            .enumerate()
+            .map(|(i, animation)| {
+                let channels = animation
+                    .get("channels")
+                    .items()
+                    .iter()
+                    .filter_map(|c| {
+                        let target = c.get("target");
+                        let joint = index(target.get("node").as_usize()?)?;
+                        let (times, values, _) = self.keys(animation, c);
+                        let path = target.get("path").as_str()?;
+                        let width = match path {
+                            "translation" | "scale" => 3,
+                            "rotation" => 4,
+                            _ => return None,
+                        };
+                        if values.len() != times.len() * width {
+                            panic!(
+                                "{}: {} channel with {} values for {} keyframes",
+                                self.path,
+                                path,
+                                values.len(),
+                                times.len()
+                            );
+                        }
+                        let keys = match path {
+                            "translation" => {
+                                Keys::Translation(values.chunks(3).map(vec3).collect())
+                            }
+                            "rotation" => Keys::Rotation(
+                                values
+                                    .chunks(4)
+                                    .map(|q| Quat(q[0], q[1], q[2], q[3]).normalize())
+                                    .collect(),
+                            ),
+                            "scale" => Keys::Scale(values.chunks(3).map(vec3).collect()),
+                            _ => return None,
+                        };
+                        Some(Channel { joint, times, keys })
+                    })
+                    .collect();
+                Clip {
+                    name: animation
+                        .get("name")
+                        .as_str()
+                        .map_or(format!("Animation {}", i), |n| n.to_string()),
+                    duration: self.duration(animation),
+                    channels,
+                }
+            })
+            .collect();
+
+        Skin {
+            skeleton: Skeleton {
+                joints: skeleton_joints,
+                root: root.unwrap_or_else(|| JointPose::new().matrix()),
+            },
+            joints: joints
+                .iter()
+                .zip(weights.iter())
+                .map(|(j, w)| {
+                    [0, 1, 2, 3].map(|k| match remap.get(j[k]) {
+                        Some(joint) => *joint,
+                        // unweighted slots are often left pointing anywhere
+                        None if w[k] == 0.0 => 0,
+                        None => panic!(
+                            "{}: vertex joint {} past the {} joints of the skin",
+                            self.path,
+                            j[k],
+                            nodes.len()
+                        ),
+                    })
+                })
+                .collect(),
+            weights,
+            clips,
+        }
+    }
+
+    // a morph clip per animation, the mesh weights held when one doesn't animate the node
+    fn morph_clips(&self, node: usize, fixed: &[f32]) -> Vec<MorphClip> {
+        self.json
+            .get("animations")
+            .items()
+            .iter()
+            .map(|animation| {
+                let channel = animation.get("channels").items().iter().find(|c| {
+                    let target = c.get("target");
+                    target.get("node").as_usize() == Some(node)
+                        && target.get("path").as_str() == Some("weights")
+                });
+                match channel {
+                    Some(c) => {
+                        let (times, values, _) = self.keys(animation, c);
+                        let per_key = values.len() / times.len().max(1);
+                        MorphClip {
+                            duration: self.duration(animation),
+                            weights: values.chunks(per_key.max(1)).map(|w| w.to_vec()).collect(),
+                            times,
+                        }
+                    }
+                    None => MorphClip {
+                        duration: 0.0,
+                        times: vec![0.0],
+                        weights: vec![fixed.to_vec()],
+                    },
+                }
+            })
+            .collect()
+    }
+
+    // the parent of every node. node, mesh, skin and child indices are checked
+    // and cycles rejected here so the rest can index and walk up freely
+    fn hierarchy(&self) -> Vec<Option<usize>> {
+        let nodes = self.json.get("nodes").items();
+        let indices = |j: &Json, key: &str| -> Vec<usize> {
+            j.get(key)
+                .items()
+                .iter()
+                .filter_map(|i| i.as_usize())
+                .collect()
+        };
+        let check = |i: usize, of: &str, what: String| {
+            let len = self.json.get(of).items().len();
+            if i >= len {
+                panic!("{}: {} is {}, past the {} {}", self.path, what, i, len, of);
+            }
+        };
+        let mut parents = vec![None; nodes.len()];
+        for (i, node) in nodes.iter().enumerate() {
+            for c in indices(node, "children") {
+                check(c, "nodes", format!("child of node {}", i));
+                if parents[c].replace(i).is_some() {
+                    panic!("{}: node {} has more than one parent", self.path, c);
+                }
+            }
+            if let Some(m) = node.get("mesh").as_usize() {
+                check(m, "meshes", format!("mesh of node {}", i));
+            }
+            if let Some(s) = node.get("skin").as_usize() {
+                check(s, "skins", format!("skin of node {}", i));
+            }
+        }
+        for (i, skin) in self.json.get("skins").items().iter().enumerate() {
+            for j in indices(skin, "joints") {
+                check(j, "nodes", format!("joint of skin {}", i));
+            }
+        }
+        for (i, scene) in self.json.get("scenes").items().iter().enumerate() {
+            for n in indices(scene, "nodes") {
+                check(n, "nodes", format!("root of scene {}", i));
+            }
+        }
+        // walks up from every node, stopping at nodes an earlier walk went through
+        let mut walk = vec![0; nodes.len()];
+        for n in 0..nodes.len() {
+            let mut up = Some(n);
+            while let Some(u) = up.filter(|u| walk[*u] == 0) {
+                walk[u] = n + 1;
+                up = parents[u];
+            }
+            if up.is_some_and(|u| walk[u] == n + 1) {
+                panic!("{}: node {} is its own ancestor", self.path, up.unwrap());
+            }
+        }
+        parents
+    }
+
+    fn scene(&self) -> Scene {
+        let nodes = self.json.get("nodes").items();
+        let parents = self.hierarchy();
+        let scene = self
+            .json
+            .get("scenes")
+            .at(self.json.get("scene").as_usize().unwrap_or(0));
+        let roots: Vec<usize> = if scene.is_null() {
+            (0..nodes.len()).filter(|n| parents[*n].is_none()).collect()
+        } else {
+            scene
+                .get("nodes")
+                .items()
+                .iter()
+                .filter_map(|n| n.as_usize())
+                .collect()
+        };
+
+        // parents before children, with the instance each node's group ends up as
+        let mut order = Vec::new();
+        let mut stack: Vec<usize> = roots.into_iter().rev().collect();
+        while let Some(n) = stack.pop() {
+            if order.contains(&n) {
+                continue;
+            }
+            order.push(n);
+            stack.extend(
+                nodes[n]
+                    .get("children")
+                    .items()
+                    .iter()
+                    .rev()
+                    .filter_map(|c| c.as_usize()),
+            );
+        }
+        let mut world = vec![JointPose::new().matrix(); nodes.len()];
+        for n in order.iter() {
+            let local = self.pose(&nodes[*n]).matrix();
+            world[*n] = match parents[*n] {
+                Some(p) => world[p].mul(&local),
+                None => local,
+            };
+        }
+
+        let mut out = Scene {
+            models: Vec::new(),
+            instances: Vec::new(),
+        };
+        let mut groups = vec![None; nodes.len()];
+        // models of meshes that are neither skinned nor morphed are shared between nodes
+        let mut shared: HashMap<usize, Vec<usize>> = HashMap::new();
+        for n in order.iter() {
+            let node = &nodes[*n];
+            let pose = self.pose(node);
+            out.instances.push(Instance {
+                parent: parents[*n].and_then(|p| groups[p]),
+                transform: Transform {
+                    translation: pose.translation,
+                    rotation: euler(&pose.rotation),
+                    scale: pose.scale,
+                },
+                ..Instance::group()
+            });
+            let group = out.instances.len() - 1;
+            groups[*n] = Some(group);
+
+            let m = match node.get("mesh").as_usize() {
+                Some(m) => m,
+                None => continue,
+            };
+            let mesh = self.json.get("meshes").at(m);
+            let weights = node.get("weights").floats();
+            let weights = if weights.is_empty() {
+                mesh.get("weights").floats()
+            } else {
+                weights
+            };
+            let skin = node
+                .get("skin")
+                .as_usize()
+                .map(|s| self.json.get("skins").at(s));
+            let models = match shared.get(&m) {
+                Some(models) => models.clone(),
+                None => {
+                    let mut models = Vec::new();
+                    let mut unique = false;
+                    for p in mesh.get("primitives").items() {
+                        let Primitive { mut model, joints } = match self.primitive(p) {
+                            Some(p) => p,
+                            None => continue,
+                        };
+                        if let Some(morph) = model.morph.as_mut() {
+                            morph.clips = self.morph_clips(*n, &weights);
+                            unique = true;
+                        }
+                        if let (Some(skin), Some((joints, weights))) = (skin, joints) {
+                            model.skin =
+                                Some(self.skin(skin, &parents, &world, *n, joints, weights));
+                            unique = true;
+                        }
+                        out.models.push(model);
+                        models.push(out.models.len() - 1);
+                    }
+                    if !unique {
+                        shared.insert(m, models.clone());
+                    }
+                    models
+                }
+            };
+            for model in models {
+                out.instances.push(Instance {
+                    parent: Some(group),
+                    weights: weights.clone(),
+                    ..Instance::new(model)
+                });
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::la::look_at;
+
+    // a triangle in an embedded buffer, positions then u16 indices padded to 4 bytes
+    fn triangle(view_length: usize) -> String {
+        r#"{
+            "asset": {"version": "2.0"},
+            "buffers": [{
+                "byteLength": 44,
+                "uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIAAAA="
+            }],
+            "bufferViews": [
+                {"buffer": 0, "byteOffset": 0, "byteLength": VIEW},
+                {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+            ],
+            "accessors": [
+                {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"},
+                {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+            ],
+            "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1}]}],
+            "nodes": [{"mesh": 0, "translation": [0, 0, 1]}],
+            "scenes": [{"nodes": [0]}],
+            "scene": 0
+        }"#
+        .replace("VIEW", &view_length.to_string())
+    }
+
+    #[test]
+    fn embedded_buffer() {
+        let scene = from_bytes("triangle.gltf", triangle(36).as_bytes());
+        assert_eq!(scene.models.len(), 1);
+        let model = &scene.models[0];
+        assert_eq!(model.num_faces(), 1);
+        let positions: Vec<(f32, f32, f32)> = (0..3)
+            .map(|v| {
+                let Vec3f(x, y, z) = model.vertex(0, v);
+                (x, y, z)
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 1.0, 0.0)]
+        );
+        let (_, _, model_m) = scene.visible().next().unwrap();
+        let Vec3f(x, y, z) = look_at(&model_m, &Vec3f(0.0, 0.0, 0.0));
+        assert_eq!((x, y, z), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "triangle.gltf: buffer view 0 ends at 48")]
+    fn view_past_the_buffer() {
+        from_bytes("triangle.gltf", triangle(48).as_bytes());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "triangle.gltf: accessor 2 has 1 elements of 2 for TEXCOORD_0, which needs 3 of [2]"
+    )]
+    fn attribute_count_past_the_positions() {
+        let json = triangle(36)
+            .replace(r#""POSITION": 0"#, r#""POSITION": 0, "TEXCOORD_0": 2"#)
+            .replace(
+                r#""type": "SCALAR"}"#,
+                r#""type": "SCALAR"},
+                {"bufferView": 0, "componentType": 5126, "count": 1, "type": "VEC2"}"#,
+            );
+        from_bytes("triangle.gltf", json.as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "triangle.gltf: accessor 0 reads past the 36 bytes")]
+    fn huge_count_is_checked_before_allocating() {
+        let json = triangle(36).replace(
+            r#""count": 3, "type": "VEC3""#,
+            r#""count": 4000000000000000, "type": "VEC3""#,
+        );
+        from_bytes("triangle.gltf", json.as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "triangle.gltf: child of node 0 is 7, past the 1 nodes")]
+    fn child_past_the_nodes() {
+        let json = triangle(36).replace(r#""mesh": 0,"#, r#""mesh": 0, "children": [7],"#);
+        from_bytes("triangle.gltf", json.as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "is its own ancestor")]
+    fn cyclic_nodes() {
+        let json = triangle(36).replace(
+            r#""nodes": [{"mesh": 0, "translation": [0, 0, 1]}]"#,
+            r#""nodes": [{"mesh": 0, "children": [1]}, {"children": [0]}]"#,
+        );
+        from_bytes("triangle.gltf", json.as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "triangle.gltf: bad json: trailing characters")]
+    fn bad_json_names_the_file() {
+        from_bytes("triangle.gltf", format!("{}}}", triangle(36)).as_bytes());
+    }
+
+    #[test]
+    fn base64_variants() {
+        assert_eq!(base64("aGVsbG8="), b"hello");
+        assert_eq!(base64("aGk"), b"hi");
+        assert_eq!(base64("+/8="), [0xfb, 0xff]);
+        assert_eq!(base64("-_8"), [0xfb, 0xff]);
+        assert_eq!(base64(""), b"");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    camera::Camera,
+    gbuffer::GBuffer,
+    la::{
+        eye, interpolate, interpolatev, look_at, normal_matrix, persp, to_screen_space, Matrix,
+        MatrixI, Vec3f,
+    },
+    model::Model,
+    tga::{Color, Image},
+};
+
+// glyph length relative to the bounding box diagonal
+const SCALE: f32 = 0.03;
+// how far behind the shaded surface a glyph pixel still counts as visible, for
+// the head and scaled with the framing
+const DEPTH_BIAS: f32 = 0.002;
+
+// bgr, green for normals from the file, yellow for generated ones, blue for faces
+const FILE_NORMAL: Color = Color(80, 220, 80);
+const GENERATED_NORMAL: Color = Color(60, 220, 230);
+const FACE_NORMAL: Color = Color(230, 120, 60);
+
+// short segments along vertex and face normals, drawn over the final image
+// and depth tested against the geometry in the gbuffer
+pub fn draw(
+    out: &mut Image,
+    gbuffer: &GBuffer,
+    model: &Model,
+    model_m: &Matrix<4, 4>,
+    camera: &Camera,
+) {
+    let lookat_m = &camera.look_at_m();
+    let wf = &model.model;
+    let (min, max) = wf.bounds();
+    let (min, max) = (look_at(model_m, &min), look_at(model_m, &max));
+    let len = max.sub(&min).length() * SCALE;
+    let model_mi = normal_matrix(model_m);
+
+    let mut segment = |p: &Vec3f, n: &Vec3f, color: Color| {
+        let p = look_at(model_m, p);
+        let n: Vec3f = model_mi.mul(&n.embed::<4>(0.0)).into();
+        let a = look_at(lookat_m, &p);
+        let b = look_at(lookat_m, &p.add(&n.normalize().mulf(len)));
+        line(out, gbuffer, camera, &a, &b, color);
+    };
+
+    // corners sharing a normal are one mesh vertex, the mesh generates them
+    // when the file has none
+    let color = if wf.normal_indices.is_empty() {
+        GENERATED_NORMAL
+    } else {
+        FILE_NORMAL
+    };
+    for v in model.mesh.vertices.iter() {
+        segment(&v.position, &v.normal.normalize(), color);
+    }
+
+    for (i, (vertices, _)) in wf.faces.iter().enumerate() {
+        let center = vertices
+            .iter()
+            .fold(Vec3f::zeroed(), |c, v| c.add(&wf.vertices[*v as usize]))
+            .mulf(1.0 / 3.0);
+        segment(&center, &wf.face_normal(i), FACE_NORMAL);
+    }
+}
+
+// a and b are in view space, cut at the near plane so that an end at the eye
+// doesn't project to infinity
+fn line(out: &mut Image, gbuffer: &GBuffer, camera: &Camera, a: &Vec3f, b: &Vec3f, color: Color) {
+    let near = eye(5.0).2 - camera.near;
+    let cut = |a: &Vec3f, b: &Vec3f| interpolatev(a, b, (near - a.2) / (b.2 - a.2));
+    let (a, b) = match (a.2 > near, b.2 > near) {
+        (false, false) => (*a, *b),
+        (false, true) => (*a, cut(a, b)),
+        (true, false) => (cut(b, a), *b),
+        (true, true) => return,
+    };
+    let bias = DEPTH_BIAS * camera.scale();
+    let sa = to_screen_space(&persp(5.0, &a), out.width, out.height);
+    let sb = to_screen_space(&persp(5.0, &b), out.width, out.height);
+    let steps = (sb.0 - sa.0).abs().max((sb.1 - sa.1).abs()).ceil().max(1.0) as i32;
+    for i in 0..=steps {
+        let t = i as f32 / steps as f32;
+        let x = interpolate(sa.0, sb.0, t).round() as i32;
+        let y = interpolate(sa.1, sb.1, t).round() as i32;
+        if x < 0 || y < 0 || x >= out.width || y >= out.height {
+            continue;
+        }
+        // short enough for view z to be interpolated linearly
+        let z = interpolate(a.2, b.2, t);
+        if gbuffer.is_empty(x, y) || z + bias >= gbuffer.depth_at(x, y) {
+            out.set_pixel(x, y, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_is_cut_at_the_near_plane() {
+        let mut out = Image::new(100, 100);
+        let gbuffer = GBuffer::new(100, 100);
+        let camera = Camera::new();
+        let color = Color(255, 255, 255);
+        // the far end is just short of the eye, right of the view uncut
+        let a = Vec3f(0.1, 0.0, 0.0);
+        let b = Vec3f(0.1, 0.0, eye(5.0).2 - 1e-4);
+        line(&mut out, &gbuffer, &camera, &a, &b, color);
+        let cut = Vec3f(0.1, 0.0, eye(5.0).2 - camera.near);
+        let end = to_screen_space(&persp(5.0, &cut), 100, 100).0.round() as i32;
+        let drawn: Vec<i32> = (0..100).filter(|x| out.pixel_at(*x, 50).0 == 255).collect();
+        assert_eq!(drawn.last(), Some(&end));
+
+        // nothing in front of the near plane
+        let mut out = Image::new(100, 100);
+        line(
+            &mut out,
+            &gbuffer,
+            &camera,
+            &b,
+            &Vec3f(0.0, 0.0, 5.0),
+            color,
+        );
+        assert!((0..100).all(|x| out.pixel_at(x, 50).0 == 0));
+    }
+}
@@ -0,0 +1,283 @@
+use crate::{
+    la::{interpolatev, Vec3f},
+    tga::{Color, Image},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    Clamp,
+    Reinhard,
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(&self, c: &Vec3f) -> Vec3f {
+        match self {
+            ToneMap::Clamp => *c,
+            ToneMap::Reinhard => Vec3f(c.0 / (1.0 + c.0), c.1 / (1.0 + c.1), c.2 / (1.0 + c.2)),
+            ToneMap::Aces => Vec3f(aces(c.0), aces(c.1), aces(c.2)),
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn next(&self) -> Self {
+        match self {
+            ToneMap::Clamp => ToneMap::Reinhard,
+            ToneMap::Reinhard => ToneMap::Aces,
+            ToneMap::Aces => ToneMap::Clamp,
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMap::Clamp => "Clamp",
+            ToneMap::Reinhard => "Reinhard",
+            ToneMap::Aces => "ACES",
+        }
+    }
+
+    // clamp, reinhard or aces
+    pub fn parse(s: &str) -> ToneMap {
+        match s.to_lowercase().as_str() {
+            "clamp" => ToneMap::Clamp,
+            "reinhard" => ToneMap::Reinhard,
+            "aces" => ToneMap::Aces,
+            _ => panic!("bad tone map {}", s),
+        }
+    }
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: f32) -> f32 {
+    let x = x.max(0.0);
+    ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).min(1.0)
+}
+
+// linear floating point framebuffer, values above 1.0 are fine
+pub struct HdrImage {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<Vec3f>,
+}
+
+impl HdrImage {
+    pub fn new(width: i32, height: i32) -> HdrImage {
+        HdrImage {
+            width,
+            height,
+            data: vec![Vec3f::zeroed(); (width * height) as usize],
+        }
+    }
+
+    // decodes srgb image into linear colors
+    pub fn from_image(img: &Image) -> HdrImage {
+        HdrImage {
+            width: img.width,
+            height: img.height,
+            data: img.data.iter().map(|c| c.to_linear()).collect(),
+        }
+    }
+
+    // radiance .hdr (rgbe), flat and new style run length encoded scanlines
+    pub fn from_radiance(v: Vec<u8>) -> Result<Self, String> {
+        let mut pos = 0;
+        let mut line = || {
+            if pos >= v.len() {
+                return None;
+            }
+            let start = pos;
+            while pos < v.len() && v[pos] != b'\n' {
+                pos += 1;
+            }
+            pos += 1;
+            Some(String::from_utf8_lossy(&v[start..pos - 1]).to_string())
+        };
+        // header ends with an empty line, resolution goes right after it
+        let no_end = || "no end of the header".to_string();
+        while !line().ok_or_else(no_end)?.is_empty() {}
+        let resolution = line().ok_or_else(no_end)?;
+        let (height, width) = match resolution.split_ascii_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", h, "+X", w] => (h.parse::<i32>(), w.parse::<i32>()),
+            _ => return Err(format!("unsupported resolution {}", resolution)),
+        };
+        let (height, width) = match (height, width) {
+            (Ok(h), Ok(w)) if h > 0 && w > 0 => (h, w),
+            _ => return Err(format!("bad resolution {}", resolution)),
+        };
+
+        let truncated = || "truncated pixel data".to_string();
+        let mut rgbe = vec![[0u8; 4]; (width * height) as usize];
+        for y in 0..height as usize {
+            let row = &mut rgbe[y * width as usize..(y + 1) * width as usize];
+            let rle = (8..0x8000).contains(&width) && v.get(pos..pos + 2) == Some(&[2, 2]);
+            if !rle {
+                for p in row.iter_mut() {
+                    p.copy_from_slice(v.get(pos..pos + 4).ok_or_else(truncated)?);
+                    pos += 4;
+                }
+                continue;
+            }
+            pos += 4;
+            for c in 0..4 {
+                let mut x = 0;
+                while x < width as usize {
+                    let count = *v.get(pos).ok_or_else(truncated)? as usize;
+                    pos += 1;
+                    // runs are above 128, literal spans below
+                    let (n, run) = if count > 128 {
+                        (count - 128, true)
+                    } else {
+                        (count, false)
+                    };
+                    if n == 0 || x + n > width as usize {
+                        return Err(format!("run of {} past the end of scanline {}", n, y));
+                    }
+                    for p in row[x..x + n].iter_mut() {
+                        p[c] = *v.get(pos).ok_or_else(truncated)?;
+                        if !run {
+                            pos += 1;
+                        }
+                    }
+                    if run {
+                        pos += 1;
+                    }
+                    x += n;
+                }
+            }
+        }
+
+        let data = rgbe
+            .iter()
+            .map(|&[r, g, b, e]| {
+                if e == 0 {
+                    Vec3f::zeroed()
+                } else {
+                    let f = 2f32.powi(e as i32 - 136);
+                    Vec3f(r as f32 * f, g as f32 * f, b as f32 * f)
+                }
+            })
+            .collect();
+        Ok(HdrImage {
+            width,
+            height,
+            data,
+        })
+    }
+
+    pub fn pixel_at(&self, x: i32, y: i32) -> Vec3f {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return Vec3f::zeroed();
+        }
+        self.data[(x + y * self.width) as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, c: Vec3f) {
+        self.data[(x + y * self.width) as usize] = c;
+    }
+
+    // bilinear, u wraps around and v is clamped
+    pub fn sample(&self, u: f32, v: f32) -> Vec3f {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let wrap = |x: i32| x.rem_euclid(self.width);
+        let (x0, y0) = (x0 as i32, y0 as i32);
+        let y1 = (y0 + 1).min(self.height - 1);
+        let top = interpolatev(
+            &self.pixel_at(wrap(x0), y0),
+            &self.pixel_at(wrap(x0 + 1), y0),
+            tx,
+        );
+        let bottom = interpolatev(
+            &self.pixel_at(wrap(x0), y1),
+            &self.pixel_at(wrap(x0 + 1), y1),
+            tx,
+        );
+        interpolatev(&top, &bottom, ty)
+    }
+
+    // box filter, used to get small images to convolve
+    pub fn downsample(&self, width: i32, height: i32) -> HdrImage {
+        let mut res = HdrImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let (x0, x1) = (
+                    x * self.width / width,
+                    ((x + 1) * self.width / width).max(x * self.width / width + 1),
+                );
+                let (y0, y1) = (
+                    y * self.height / height,
+                    ((y + 1) * self.height / height).max(y * self.height / height + 1),
+                );
+                let mut total = Vec3f::zeroed();
+                for yy in y0..y1 {
+                    for xx in x0..x1 {
+                        total = total.add(&self.pixel_at(xx, yy));
+                    }
+                }
+                res.set_pixel(x, y, total.mulf(1.0 / ((x1 - x0) * (y1 - y0)) as f32));
+            }
+        }
+        res
+    }
+
+    // exposure is in stops, keeps the result linear
+    pub fn tone_map(&mut self, exposure: f32, tone_map: ToneMap) {
+        let k = 2f32.powf(exposure);
+        for c in self.data.iter_mut() {
+            *c = tone_map.apply(&c.mulf(k));
+        }
+    }
+
+    // exposure is in stops, result is srgb encoded
+    pub fn to_image(&self, exposure: f32, tone_map: ToneMap) -> Image {
+        let k = 2f32.powf(exposure);
+        Image {
+            width: self.width,
+            height: self.height,
+            data: self
+                .data
+                .iter()
+                .map(|c| Color::from_linear(&tone_map.apply(&c.mulf(k))))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn radiance(scanline: &[u8]) -> Vec<u8> {
+        let mut v = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        v.extend_from_slice(scanline);
+        v
+    }
+
+    #[test]
+    fn run_length_scanline() {
+        let img = HdrImage::from_radiance(radiance(&[
+            2, 2, 0, 8, 136, 128, 136, 64, 136, 32, 136, 129,
+        ]))
+        .unwrap();
+        assert_eq!((img.width, img.height), (8, 1));
+        let Vec3f(r, g, b) = img.pixel_at(7, 0);
+        assert_eq!((r, g, b), (1.0, 0.5, 0.25));
+    }
+
+    #[test]
+    fn run_past_the_scanline() {
+        let err = HdrImage::from_radiance(radiance(&[2, 2, 0, 8, 137, 128]))
+            .err()
+            .unwrap();
+        assert!(err.contains("past the end"), "{}", err);
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(HdrImage::from_radiance(radiance(&[2, 2, 0, 8, 136])).is_err());
+        assert!(HdrImage::from_radiance(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n".to_vec()).is_err());
+    }
+}
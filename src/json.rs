@@ -0,0 +1,291 @@
+// just enough json to read gltf, malformed input is an error saying where
+#[derive(Debug, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    pub fn parse(s: &str) -> Result<Json, String> {
+        let mut p = Parser {
+            s: s.as_bytes(),
+            pos: 0,
+        };
+        let v = p.value()?;
+        p.ws();
+        if p.pos != p.s.len() {
+            return Err(format!("trailing characters at {}", p.pos));
+        }
+        Ok(v)
+    }
+
+    // missing keys and indices are null so lookups can be chained
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&NULL, |(_, v)| v),
+            _ => &NULL,
+        }
+    }
+
+    pub fn at(&self, i: usize) -> &Json {
+        self.items().get(i).unwrap_or(&NULL)
+    }
+
+    pub fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Json::Number(n) => Some(*n as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(n) if *n >= 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    // numbers of an array like a matrix or a color factor
+    pub fn floats(&self) -> Vec<f32> {
+        self.items().iter().filter_map(|v| v.as_f32()).collect()
+    }
+}
+
+struct Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn ws(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8, String> {
+        self.ws();
+        self.s
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| "unexpected end of json".to_string())
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek()? != c {
+            return Err(format!("expected '{}' at {}", c as char, self.pos));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, word: &str, v: Json) -> Result<Json, String> {
+        if !self.s[self.pos..].starts_with(word.as_bytes()) {
+            return Err(format!("unexpected token at {}", self.pos));
+        }
+        self.pos += word.len();
+        Ok(v)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek()? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.peek()? == b'}' {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    if self.peek()? == b',' {
+                        self.pos += 1;
+                    } else {
+                        self.expect(b'}')?;
+                        return Ok(Json::Object(fields));
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek()? == b']' {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    if self.peek()? == b',' {
+                        self.pos += 1;
+                    } else {
+                        self.expect(b']')?;
+                        return Ok(Json::Array(items));
+                    }
+                }
+            }
+            b'"' => Ok(Json::String(self.string()?)),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'n' => self.literal("null", Json::Null),
+            _ => {
+                let start = self.pos;
+                while self.pos < self.s.len()
+                    && matches!(
+                        self.s[self.pos],
+                        b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'
+                    )
+                {
+                    self.pos += 1;
+                }
+                let n = std::str::from_utf8(&self.s[start..self.pos]).unwrap();
+                n.parse()
+                    .map(Json::Number)
+                    .map_err(|_| format!("bad number at {}", start))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut bytes = Vec::new();
+        let unterminated = || "unterminated json string".to_string();
+        loop {
+            let c = *self.s.get(self.pos).ok_or_else(unterminated)?;
+            self.pos += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let e = *self.s.get(self.pos).ok_or_else(unterminated)?;
+                    self.pos += 1;
+                    let c = match e {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let code = self.hex()?;
+                            // surrogate pairs come as two escapes, a high one without
+                            // a low one after it is replaced and the next escape kept
+                            let low = if self.s[self.pos..].starts_with(b"\\u") {
+                                self.hex_at(self.pos + 2)
+                                    .filter(|low| (0xdc00..0xe000).contains(low))
+                            } else {
+                                None
+                            };
+                            let code = match low {
+                                Some(low) if (0xd800..0xdc00).contains(&code) => {
+                                    self.pos += 6;
+                                    0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00)
+                                }
+                                _ => code,
+                            };
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        c => c as char,
+                    };
+                    let mut buf = [0; 4];
+                    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    fn hex(&mut self) -> Result<u32, String> {
+        if self.pos + 4 > self.s.len() {
+            return Err("unterminated json string".to_string());
+        }
+        let code = self.hex_at(self.pos).unwrap_or(0xfffd);
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn hex_at(&self, pos: usize) -> Option<u32> {
+        let digits = std::str::from_utf8(self.s.get(pos..pos + 4)?).ok()?;
+        u32::from_str_radix(digits, 16).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> String {
+        Json::parse(s).unwrap().as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            string(r#""a\"b\\c\/d\n\t\r\b\f""#),
+            "a\"b\\c/d\n\t\r\u{8}\u{c}"
+        );
+        assert_eq!(string(r#""\u00e9\u20AC""#), "\u{e9}\u{20ac}");
+    }
+
+    #[test]
+    fn surrogates() {
+        assert_eq!(string(r#""\ud83d\ude00""#), "\u{1f600}");
+        // a high surrogate without a low one keeps the escape that follows
+        assert_eq!(string(r#""\ud83d\u0041""#), "\u{fffd}A");
+        assert_eq!(string(r#""\ud83d!""#), "\u{fffd}!");
+        assert_eq!(string(r#""\ude00""#), "\u{fffd}");
+    }
+
+    #[test]
+    fn lookups() {
+        let v = Json::parse(r#"{"a": [1, 2.5, -3e1], "b": {"c": true}, "d": null}"#).unwrap();
+        assert_eq!(v.get("a").floats(), vec![1.0, 2.5, -30.0]);
+        assert_eq!(v.get("b").get("c").as_bool(), Some(true));
+        assert!(v.get("d").is_null() && v.get("e").is_null() && v.get("a").at(5).is_null());
+    }
+
+    #[test]
+    fn malformed() {
+        let err = |s: &str| Json::parse(s).err().unwrap();
+        assert_eq!(err(r#"{"a": 1} x"#), "trailing characters at 9");
+        assert_eq!(err(r#"{"a": -}"#), "bad number at 6");
+        assert_eq!(err(r#"{"a": [1, 2"#), "unexpected end of json");
+        assert_eq!(err(r#"{"a": "b"#), "unterminated json string");
+        assert_eq!(err(r#"{"a" 1}"#), "expected ':' at 5");
+        assert_eq!(err("tru"), "unexpected token at 0");
+    }
+}
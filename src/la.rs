@@ -6,6 +6,18 @@ impl Vec3f {
         Vec3f(0.0, 0.0, 0.0)
     }
 
+    // "x,y,z", for settings given as text
+    pub fn parse(s: &str) -> Option<Self> {
+        let v: Vec<f32> = s
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        match v[..] {
+            [x, y, z] => Some(Vec3f(x, y, z)),
+            _ => None,
+        }
+    }
+
     pub fn embed<const L: usize>(&self, i: f32) -> Matrix<1, L> {
         assert!(L > 3);
         let mut v = [[i]; L];
@@ -29,6 +41,10 @@ impl Vec3f {
         Vec3f(self.0 / mag, self.1 / mag, self.2 / mag)
     }
 
+    pub fn length(&self) -> f32 {
+        (self.0 * self.0 + self.1 * self.1 + self.2 * self.2).sqrt()
+    }
+
     pub fn sub(&self, v: &Vec3f) -> Self {
         Vec3f(self.0 - v.0, self.1 - v.1, self.2 - v.2)
     }
@@ -45,6 +61,11 @@ impl Vec3f {
         Vec3f(self.0 * v, self.1 * v, self.2 * v)
     }
 
+    // component-wise, mostly for colors
+    pub fn mulv(&self, v: &Vec3f) -> Vec3f {
+        Vec3f(self.0 * v.0, self.1 * v.1, self.2 * v.2)
+    }
+
     pub fn rotate(&self, x: f32, y: f32) -> Vec3f {
         let xm: Matrix<3, 3> = [
             [1.0, 0.0, 0.0],
@@ -68,11 +89,129 @@ impl Into<Matrix<1, 3>> for &Vec3f {
     }
 }
 
+// unit quaternion x, y, z, w for rotations that interpolate
+#[derive(Clone, Debug, Copy)]
+pub struct Quat(pub f32, pub f32, pub f32, pub f32);
+
+impl Quat {
+    pub fn identity() -> Self {
+        Quat(0.0, 0.0, 0.0, 1.0)
+    }
+
+    pub fn from_axis_angle(axis: &Vec3f, angle: f32) -> Self {
+        let (s, c) = (angle / 2.0).sin_cos();
+        let a = axis.normalize().mulf(s);
+        Quat(a.0, a.1, a.2, c)
+    }
+
+    // rotation part of a matrix without scale
+    pub fn from_matrix(m: &Matrix<4, 4>) -> Self {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        if trace > 0.0 {
+            let s = 0.5 / (trace + 1.0).sqrt();
+            Quat(
+                (m[2][1] - m[1][2]) * s,
+                (m[0][2] - m[2][0]) * s,
+                (m[1][0] - m[0][1]) * s,
+                0.25 / s,
+            )
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quat(
+                0.25 * s,
+                (m[0][1] + m[1][0]) / s,
+                (m[0][2] + m[2][0]) / s,
+                (m[2][1] - m[1][2]) / s,
+            )
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quat(
+                (m[0][1] + m[1][0]) / s,
+                0.25 * s,
+                (m[1][2] + m[2][1]) / s,
+                (m[0][2] - m[2][0]) / s,
+            )
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quat(
+                (m[0][2] + m[2][0]) / s,
+                (m[1][2] + m[2][1]) / s,
+                0.25 * s,
+                (m[1][0] - m[0][1]) / s,
+            )
+        }
+        .normalize()
+    }
+
+    pub fn dot(&self, q: &Quat) -> f32 {
+        self.0 * q.0 + self.1 * q.1 + self.2 * q.2 + self.3 * q.3
+    }
+
+    pub fn normalize(&self) -> Self {
+        let mag = self.dot(self).sqrt();
+        Quat(self.0 / mag, self.1 / mag, self.2 / mag, self.3 / mag)
+    }
+
+    // shortest path, falls back to a normalized lerp when the rotations are close
+    pub fn slerp(&self, q: &Quat, t: f32) -> Self {
+        let d = self.dot(q);
+        let (q, d) = if d < 0.0 {
+            (Quat(-q.0, -q.1, -q.2, -q.3), -d)
+        } else {
+            (*q, d)
+        };
+        let (a, b) = if d > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = d.acos();
+            let s = theta.sin();
+            (((1.0 - t) * theta).sin() / s, (t * theta).sin() / s)
+        };
+        Quat(
+            self.0 * a + q.0 * b,
+            self.1 * a + q.1 * b,
+            self.2 * a + q.2 * b,
+            self.3 * a + q.3 * b,
+        )
+        .normalize()
+    }
+
+    pub fn matrix(&self) -> Matrix<4, 4> {
+        let Quat(x, y, z, w) = *self;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+    }
+}
+
 pub trait MatrixI<const X: usize, const Y: usize> {
     fn zeroed() -> Self;
     fn inverse(&self) -> Self
     where
         [(); X * 2]: Sized;
+    // None for singular matrices
+    fn try_inverse(&self) -> Option<Self>
+    where
+        Self: Sized,
+        [(); X * 2]: Sized;
     fn transpose(&self) -> Matrix<Y, X>;
     fn mul<const XX: usize, const YY: usize>(&self, matrix: &Matrix<XX, YY>) -> Matrix<XX, Y>;
 }
@@ -92,6 +231,14 @@ impl<const X: usize, const Y: usize> MatrixI<X, Y> for Matrix<X, Y> {
     }
 
     fn inverse(&self) -> Self
+    where
+        [(); X * 2]: Sized,
+    {
+        self.try_inverse()
+            .expect("it's a bad idea to divide by zero")
+    }
+
+    fn try_inverse(&self) -> Option<Self>
     where
         [(); X * 2]: Sized,
     {
@@ -112,7 +259,15 @@ impl<const X: usize, const Y: usize> MatrixI<X, Y> for Matrix<X, Y> {
             r
         };
         for y in 0..n {
-            assert!(aug[y][y] != 0.0f32, "it's a bad idea to divide by zero");
+            // swap in a row below when the diagonal is zero, like any right angle rotation has
+            if aug[y][y] == 0.0 {
+                if let Some(p) = (y + 1..n).find(|p| aug[*p][y] != 0.0) {
+                    aug.swap(y, p);
+                }
+            }
+            if aug[y][y] == 0.0 {
+                return None;
+            }
             for x in 0..n {
                 if x != y {
                     let r = aug[x][y] / aug[y][y];
@@ -136,7 +291,7 @@ impl<const X: usize, const Y: usize> MatrixI<X, Y> for Matrix<X, Y> {
             }
         }
 
-        res
+        Some(res)
     }
 
     fn transpose(&self) -> Matrix<Y, X> {
@@ -183,6 +338,34 @@ pub fn interpolatev(a: &Vec3f, b: &Vec3f, t: f32) -> Vec3f {
     )
 }
 
+pub fn smoothstep(e0: f32, e1: f32, x: f32) -> f32 {
+    let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// xorshift, good enough for sampling kernels and doesn't pull any dependencies
+pub struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
 pub fn barycentric(a: &Vec3f, b: &Vec3f, c: &Vec3f, p: (f32, f32)) -> Vec3f {
     let cross =
         Vec3f(c.0 - a.0, b.0 - a.0, a.0 - p.0).cross(&Vec3f(c.1 - a.1, b.1 - a.1, a.1 - p.1));
@@ -201,6 +384,17 @@ pub fn persp(c: f32, v1: &Vec3f) -> Vec3f {
     )
 }
 
+// view space position of the eye persp projects to
+pub fn eye(c: f32) -> Vec3f {
+    Vec3f(0.0, 0.0, 1.08 * c)
+}
+
+// direction of the camera ray going through the normalized screen point,
+// inverse of persp
+pub fn view_ray(c: f32, x: f32, y: f32) -> Vec3f {
+    Vec3f(x * 1.08, y * 1.08, 0.0).sub(&eye(c)).normalize()
+}
+
 pub fn get_look_at(p: &Vec3f, c: &Vec3f) -> Matrix<4, 4> {
     let up = Vec3f(0.0, 1.0, 0.0);
 
@@ -230,6 +424,26 @@ pub fn look_at(m: &Matrix<4, 4>, v: &Vec3f) -> Vec3f {
     Vec3f(r[0][0] / r[3][0], r[1][0] / r[3][0], r[2][0] / r[3][0])
 }
 
+// inverse transpose of the upper 3x3 for normals, from the cofactors so singular
+// matrices (a zero scale hides a part) still give one instead of a panic
+pub fn normal_matrix(m: &Matrix<4, 4>) -> Matrix<4, 4> {
+    let row = |y: usize| Vec3f(m[y][0], m[y][1], m[y][2]);
+    let (a, b, c) = (row(0), row(1), row(2));
+    let det = a.mul(&b.cross(&c));
+    let s = if det.abs() > f32::EPSILON {
+        1.0 / det
+    } else {
+        1.0
+    };
+    let [x, y, z] = [b.cross(&c), c.cross(&a), a.cross(&b)].map(|r| r.mulf(s));
+    [
+        [x.0, x.1, x.2, 0.0],
+        [y.0, y.1, y.2, 0.0],
+        [z.0, z.1, z.2, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
 pub fn to_screen_space(v: &Vec3f, width: i32, height: i32) -> Vec3f {
     let x0 = (v.0 + 1.) * (width - 1) as f32 / 2.;
     let y0 = (v.1 + 1.) * (height - 1) as f32 / 2.;
@@ -280,4 +494,28 @@ mod tests {
         // println!("{:?}", m2.mul(v.into()));
         // println!("{:?}", m1.transpose());
     }
+
+    #[test]
+    fn normal_matrix_of_scaled_and_singular_matrices() {
+        let m = [
+            [2.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, -3.0, 2.0],
+            [0.0, 1.0, 0.0, 3.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let (n, it) = (normal_matrix(&m), m.inverse().transpose());
+        for y in 0..3 {
+            for x in 0..3 {
+                assert!((n[y][x] - it[y][x]).abs() < 1e-6);
+            }
+        }
+        // a zero scale hides the part, its normals collapse instead of panicking
+        let zero = [
+            [0.0, 0.0, 0.0, 1.0],
+            [0.0; 4],
+            [0.0; 4],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        assert!(normal_matrix(&zero).iter().flatten().all(|v| v.is_finite()));
+    }
 }
@@ -0,0 +1,295 @@
+use std::fs;
+
+use crate::{
+    camera::Camera,
+    la::{look_at, smoothstep, Matrix, MatrixI, Vec3f},
+};
+
+#[derive(Clone, Debug)]
+pub enum Light {
+    // dir points from the surface towards the light
+    Directional {
+        dir: Vec3f,
+        color: Vec3f,
+        intensity: f32,
+    },
+    // attenuation is (constant, linear, quadratic)
+    Point {
+        pos: Vec3f,
+        color: Vec3f,
+        intensity: f32,
+        attenuation: (f32, f32, f32),
+    },
+    // dir is the axis the spot shines along, cone angles are in radians
+    Spot {
+        pos: Vec3f,
+        dir: Vec3f,
+        inner: f32,
+        outer: f32,
+        color: Vec3f,
+        intensity: f32,
+        attenuation: (f32, f32, f32),
+    },
+    Ambient {
+        color: Vec3f,
+        intensity: f32,
+    },
+}
+
+impl Light {
+    // key, fill and rim lights around the origin plus a bit of ambient
+    pub fn studio() -> Vec<Light> {
+        vec![
+            Light::Directional {
+                dir: Vec3f(1.0, -0.0, 0.5).normalize(),
+                color: Vec3f(1.0, 0.96, 0.9),
+                intensity: 1.2,
+            },
+            Light::Point {
+                pos: Vec3f(-2.0, 0.5, 1.5),
+                color: Vec3f(0.8, 0.9, 1.0),
+                intensity: 0.5,
+                attenuation: (1.0, 0.05, 0.01),
+            },
+            Light::Spot {
+                pos: Vec3f(-0.5, 1.5, -2.0),
+                dir: Vec3f(0.5, -1.5, 2.0).normalize(),
+                inner: 0.3,
+                outer: 0.6,
+                color: Vec3f(1.0, 1.0, 1.0),
+                intensity: 0.8,
+                attenuation: (1.0, 0.0, 0.0),
+            },
+            Light::Ambient {
+                color: Vec3f(1.0, 1.0, 1.0),
+                intensity: 0.3,
+            },
+        ]
+    }
+
+    // one light per line in the order of the fields, # starts a comment:
+    //   directional dx dy dz r g b intensity
+    //   point x y z r g b intensity [constant linear quadratic]
+    //   spot x y z dx dy dz inner outer r g b intensity [constant linear quadratic]
+    //   ambient r g b intensity
+    pub fn parse_file(file: &str) -> Vec<Light> {
+        let contents =
+            fs::read_to_string(file).unwrap_or_else(|e| panic!("can't read {}: {}", file, e));
+        contents
+            .lines()
+            .enumerate()
+            .filter_map(|(i, l)| {
+                Light::parse(l).unwrap_or_else(|e| panic!("{}:{}: {}", file, i + 1, e))
+            })
+            .collect()
+    }
+
+    // a line of a lights file, None for blank lines and comments
+    pub fn parse(line: &str) -> Result<Option<Light>, String> {
+        let line = line.split('#').next().unwrap_or("");
+        let mut items = line.split_ascii_whitespace();
+        let kind = match items.next() {
+            Some(kind) => kind,
+            None => return Ok(None),
+        };
+        let f: Vec<f32> = items
+            .map(|v| v.parse().map_err(|_| format!("bad number {}", v)))
+            .collect::<Result<_, _>>()?;
+        let v = |i: usize| Vec3f(f[i], f[i + 1], f[i + 2]);
+        let attenuation = |i: usize| match f.get(i..i + 3) {
+            Some(a) => (a[0], a[1], a[2]),
+            None => (1.0, 0.0, 0.0),
+        };
+        let light = match (kind, f.len()) {
+            ("directional", 7) => Light::Directional {
+                dir: v(0).normalize(),
+                color: v(3),
+                intensity: f[6],
+            },
+            ("point", 7) | ("point", 10) => Light::Point {
+                pos: v(0),
+                color: v(3),
+                intensity: f[6],
+                attenuation: attenuation(7),
+            },
+            ("spot", 12) | ("spot", 15) => Light::Spot {
+                pos: v(0),
+                dir: v(3).normalize(),
+                inner: f[6],
+                outer: f[7],
+                color: v(8),
+                intensity: f[11],
+                attenuation: attenuation(12),
+            },
+            ("ambient", 4) => Light::Ambient {
+                color: v(0),
+                intensity: f[3],
+            },
+            (kind, n) => return Err(format!("{} light with {} values", kind, n)),
+        };
+        Ok(Some(light))
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Light::Directional { .. } => "Directional",
+            Light::Point { .. } => "Point",
+            Light::Spot { .. } => "Spot",
+            Light::Ambient { .. } => "Ambient",
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn intensity(&self) -> f32 {
+        match self {
+            Light::Directional { intensity, .. }
+            | Light::Point { intensity, .. }
+            | Light::Spot { intensity, .. }
+            | Light::Ambient { intensity, .. } => *intensity,
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn set_intensity(&mut self, i: f32) {
+        match self {
+            Light::Directional { intensity, .. }
+            | Light::Point { intensity, .. }
+            | Light::Spot { intensity, .. }
+            | Light::Ambient { intensity, .. } => *intensity = i.max(0.0),
+        }
+    }
+
+    // point and spot lights set up around the head moved out with the framing,
+    // attenuation stretched so they fall off over the same part of the scene
+    pub fn framed(&self, camera: &Camera) -> Light {
+        let s = camera.scale();
+        let stretch = |(c, l, q): (f32, f32, f32)| (c, l / s, q / (s * s));
+        match self.clone() {
+            Light::Point {
+                pos,
+                color,
+                intensity,
+                attenuation,
+            } => Light::Point {
+                pos: camera.place(&pos),
+                color,
+                intensity,
+                attenuation: stretch(attenuation),
+            },
+            Light::Spot {
+                pos,
+                dir,
+                inner,
+                outer,
+                color,
+                intensity,
+                attenuation,
+            } => Light::Spot {
+                pos: camera.place(&pos),
+                dir,
+                inner,
+                outer,
+                color,
+                intensity,
+                attenuation: stretch(attenuation),
+            },
+            light => light,
+        }
+    }
+
+    // moves the light into the camera space described by the look at matrix
+    pub fn look_at(&self, m: &Matrix<4, 4>) -> Light {
+        let rotate = |v: &Vec3f| -> Vec3f { m.mul(&v.embed::<4>(0.0)).into() };
+        match self.clone() {
+            Light::Directional {
+                dir,
+                color,
+                intensity,
+            } => Light::Directional {
+                dir: rotate(&dir).normalize(),
+                color,
+                intensity,
+            },
+            Light::Point {
+                pos,
+                color,
+                intensity,
+                attenuation,
+            } => Light::Point {
+                pos: look_at(m, &pos),
+                color,
+                intensity,
+                attenuation,
+            },
+            Light::Spot {
+                pos,
+                dir,
+                inner,
+                outer,
+                color,
+                intensity,
+                attenuation,
+            } => Light::Spot {
+                pos: look_at(m, &pos),
+                dir: rotate(&dir).normalize(),
+                inner,
+                outer,
+                color,
+                intensity,
+                attenuation,
+            },
+            ambient @ Light::Ambient { .. } => ambient,
+        }
+    }
+
+    // direction towards the light and its color at the given point,
+    // ambient light has no direction
+    pub fn incident(&self, p: &Vec3f) -> Option<(Vec3f, Vec3f)> {
+        match self {
+            Light::Directional {
+                dir,
+                color,
+                intensity,
+            } => Some((*dir, color.mulf(*intensity))),
+            Light::Point {
+                pos,
+                color,
+                intensity,
+                attenuation,
+            } => {
+                let to_light = pos.sub(p);
+                let att = attenuate(attenuation, to_light.length());
+                Some((to_light.normalize(), color.mulf(intensity * att)))
+            }
+            Light::Spot {
+                pos,
+                dir,
+                inner,
+                outer,
+                color,
+                intensity,
+                attenuation,
+            } => {
+                let to_light = pos.sub(p);
+                let l = to_light.normalize();
+                let cos = l.mulf(-1.0).mul(dir);
+                let cone = smoothstep(outer.cos(), inner.cos(), cos);
+                let att = attenuate(attenuation, to_light.length());
+                Some((l, color.mulf(intensity * att * cone)))
+            }
+            Light::Ambient { .. } => None,
+        }
+    }
+
+    pub fn ambient(&self) -> Vec3f {
+        match self {
+            Light::Ambient { color, intensity } => color.mulf(*intensity),
+            _ => Vec3f::zeroed(),
+        }
+    }
+}
+
+fn attenuate(&(c, l, q): &(f32, f32, f32), d: f32) -> f32 {
+    1.0 / (c + l * d + q * d * d).max(1e-4)
+}
@@ -4,17 +4,40 @@ extern crate anyhow;
 #[cfg(not(feature = "local"))]
 extern crate yew;
 
+mod background;
+mod camera;
+mod debug;
+mod dof;
+mod env;
+mod fog;
+mod gbuffer;
+mod gltf;
+mod glyphs;
+mod hdr;
+mod json;
 mod la;
+mod light;
+mod material;
 mod model;
+mod morph;
+mod ply;
+mod png;
+mod post;
+mod scene;
 mod shader;
+mod skin;
+mod ssao;
+mod stl;
 mod tga;
+mod toon;
 #[cfg(not(feature = "local"))]
 mod web;
 #[cfg(feature = "local")]
 use crate::{
-    la::{get_look_at, look_at, Matrix, MatrixI, Vec3f},
+    la::{Matrix, MatrixI, Vec3f},
+    light::Light,
     model::Model,
-    shader::{triangle, BasicShader, Shader, ShaderConf},
+    shader::{triangle, BasicShader, Shader, ShaderConf, VertexCache},
     tga::Image,
 };
 #[cfg(not(feature = "local"))]
@@ -27,76 +50,350 @@ fn main() {
 
 #[cfg(feature = "local")]
 fn main() {
+    use background::Background;
+    use camera::Camera;
+    use debug::DebugView;
+    use env::Environment;
+    use fog::{Fog, FogMode};
+    use gbuffer::GBuffer;
+    use hdr::{HdrImage, ToneMap};
+    use material::Material;
     use model::{Model, Wavefront};
-    use shader::LightShader;
+    use morph::{Morph, MorphTarget};
+    use post::PostChain;
+    use scene::{Instance, InstanceData, Scene, Transform};
+    use shader::{CullMode, LightShader, RasterState, Winding};
+    use skin::Skin;
+    use ssao::Ssao;
+    use std::{fs, path::Path};
 
     let width: i32 = 1000;
     let height: i32 = 1000;
-    let mut out_texture = tga::Image::new(width, height);
+    let mut out_texture = HdrImage::new(width, height);
     let mut z_buffer = tga::Image::new(width, height);
     let mut light_texture = tga::Image::new(width, height);
+    let mut gbuffer = GBuffer::new(width, height);
 
-    let wavefront = Wavefront::parse_file("./res/african_head/model.obj".to_string());
-    let model_texture = tga::Image::from_file("./res/african_head/texture.tga".to_string());
-    let model_normals = tga::Image::from_file("./res/african_head/normals.tga".to_string());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // optional time in seconds to render the animations at and a gltf scene or
+    // a stl, ply or obj mesh to render instead of the head, in any order
+    let time: Option<f32> = args.iter().find_map(|a| a.parse().ok());
+    let file = |extensions: &[&str]| {
+        args.iter()
+            .filter(|a| !a.starts_with("--"))
+            .find(|a| extensions.iter().any(|e| a.to_lowercase().ends_with(e)))
+    };
+    let gltf_path = file(&[".gltf", ".glb"]);
+    let mesh_path = file(&[".stl", ".ply", ".obj"]);
+    // --name=value settings
+    let option = |name: &str| {
+        args.iter()
+            .find_map(|a| a.strip_prefix("--")?.strip_prefix(name)?.strip_prefix('='))
+    };
+    let number = |name: &str| {
+        option(name).map(|v| {
+            v.parse::<f32>()
+                .unwrap_or_else(|_| panic!("bad --{} {}", name, v))
+        })
+    };
+    // --name settings without a value
+    let flag = |name: &str| args.iter().any(|a| a.strip_prefix("--") == Some(name));
+    // --export=file.obj saves the first model in world space, with the normals it's drawn with
+    let export = option("export");
 
-    let model = Model::new(wavefront, model_normals, model_texture);
+    let mut scene = if let Some(path) = gltf_path {
+        let mut scene = gltf::load(path);
+        assert!(!scene.models.is_empty(), "{} has no meshes", path);
+        for instance in scene.instances.iter_mut() {
+            instance.clip = time.map(|_| 0);
+            instance.morph_clip = time.map(|_| 0);
+            instance.time = time.unwrap_or(0.0);
+        }
+        scene
+    } else if let Some(path) = mesh_path {
+        let lower = path.to_lowercase();
+        let wavefront = if lower.ends_with(".stl") {
+            stl::parse_file(path.to_string())
+        } else if lower.ends_with(".ply") {
+            ply::parse_file(path.to_string())
+        } else {
+            Wavefront::parse_file(path.to_string())
+        };
+        let mut model = Model::untextured(wavefront);
+        // an obj's materials are next to it
+        if let Some(mtllib) = model.model.mtllib.as_ref() {
+            model.materials = Material::load_mtl(&Path::new(path).with_file_name(mtllib));
+        }
+        Scene::single(model)
+    } else {
+        let wavefront = Wavefront::parse_file("./res/african_head/model.obj".to_string());
+        let model_texture = tga::Image::from_file("./res/african_head/texture.tga".to_string());
+        let model_normals = tga::Image::from_file("./res/african_head/normals.tga".to_string());
 
-    let camvec = Vec3f(0.5, 0.5, 1.0);
-    let cam_lookat = Vec3f(0.0, 0.0, 0.0);
-    let lookat_m = get_look_at(&camvec, &cam_lookat);
+        let mut model = Model::new(wavefront, model_normals, model_texture);
+        if let Some(mtllib) = model.model.mtllib.as_ref() {
+            model.materials = Material::load_mtl(&Path::new("./res/african_head").join(mtllib));
+        }
+        // --nod rigs the neck so the time argument has an animation to play
+        if flag("nod") {
+            model.skin = Some(Skin::nod(&model.mesh));
+        }
+        // --morphs=dir holds other shapes of the head with the same topology,
+        // a morph target per obj file played in turn by the time argument
+        if let Some(dir) = option("morphs") {
+            let mut files: Vec<_> = fs::read_dir(dir)
+                .unwrap_or_else(|e| panic!("can't read {}: {}", dir, e))
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|e| e == "obj"))
+                .collect();
+            files.sort();
+            let targets: Vec<MorphTarget> = files
+                .iter()
+                .filter_map(|p| {
+                    let target = Wavefront::parse_file(p.to_string_lossy().to_string());
+                    let target = MorphTarget::from_wavefront(&model.model, &model.mesh, &target);
+                    if target.is_none() {
+                        eprintln!("{} doesn't match the head, skipped", p.display());
+                    }
+                    target
+                })
+                .collect();
+            model.morph = Some(Morph::cycle(targets));
+        }
+
+        let mut scene = Scene::single(model);
+        scene.instances[0].clip = time.map(|_| 0);
+        scene.instances[0].time = time.unwrap_or(0.0);
+        scene.instances[0].morph_clip = time.map(|_| 0);
+        // --child adds a smaller copy of the head behind and to the left of it,
+        // parented to the first one so it follows it around
+        if flag("child") {
+            scene.instances.push(Instance {
+                parent: Some(0),
+                transform: Transform {
+                    translation: Vec3f(-1.0, -0.45, -0.4),
+                    rotation: Vec3f(0.0, 0.6, 0.0),
+                    scale: Vec3f(0.5, 0.5, 0.5),
+                },
+                ..Instance::new(0)
+            });
+        }
+        scene
+    };
+
+    // --clip=name plays the skeletal clip with that name at the time argument
+    // instead of the first one
+    if let (Some(name), Some(_)) = (option("clip"), time) {
+        let models = &scene.models;
+        let mut found = false;
+        for instance in scene.instances.iter_mut() {
+            let clip = instance
+                .model
+                .and_then(|m| models[m].skin.as_ref())
+                .and_then(|s| s.clips.iter().position(|c| c.name == name));
+            found |= clip.is_some();
+            instance.clip = clip.or(instance.clip);
+        }
+        assert!(found, "no clip named {}", name);
+    }
+
+    if let Some(path) = export {
+        // placed where the first instance of it is drawn
+        let world = (0..scene.instances.len())
+            .find(|i| scene.instances[*i].model == Some(0))
+            .map_or_else(|| Transform::new().matrix(), |i| scene.world_matrix(i));
+        scene.models[0]
+            .model
+            .with_generated_normals()
+            .transformed(&world)
+            .write_to_obj(path)
+            .unwrap();
+    }
+
+    // --env=file.hdr or --env=dir with cube faces, a procedural sky without one
+    let env = option("env").map_or_else(Environment::sky, Environment::load);
+
+    // --crowd=10 draws that many small tinted heads in rows behind the scene
+    let crowd = option("crowd").map_or_else(Vec::new, |n| {
+        scene::crowd(n.parse().unwrap_or_else(|_| panic!("bad --crowd {}", n)))
+    });
+
+    // loaded files can be any size, the head is made for the default camera and
+    // the lights and effects set up for it are scaled along with the framing
+    let camera = if gltf_path.is_some() || mesh_path.is_some() || !crowd.is_empty() {
+        Camera::new().frame(scene.bounding_sphere_with(&crowd))
+    } else {
+        Camera::new()
+    };
+    let lookat_m = camera.look_at_m();
     let lookat_mi = lookat_m.inverse().transpose();
-    let light_dir: Vec3f = look_at(&lookat_m, &Vec3f(1.0, -0.0, 0.5).normalize()).normalize();
+    // --lights=file replaces the studio lights, see Light::parse for the format
+    let lights: Vec<Light> = option("lights")
+        .map_or_else(Light::studio, Light::parse_file)
+        .iter()
+        .map(|l| l.framed(&camera).look_at(&lookat_m))
+        .collect();
+
+    // --background=skybox, solid:r,g,b, gradient:r,g,b:r,g,b or image:file.tga
+    let background = option("background").map_or(Background::Environment, Background::parse);
+
+    // --post=bloom,tone-mapping,... picks the post passes and their order
+    let post = option("post").map_or_else(PostChain::new, PostChain::parse);
+    // --tone-map=clamp, reinhard or aces for the tone mapping pass, --exposure=
+    // in stops before it
+    let tone_map = option("tone-map").map_or(ToneMap::Aces, ToneMap::parse);
+    let exposure = number("exposure").unwrap_or(0.0);
+
+    // --fog=off, linear, exp or exp2 with --fog-density=, --fog-range=start,end
+    // (distances from the eye) and --fog-color=r,g,b, --height-fog=density adds
+    // fog that thins out upwards, --fog-per-fragment fogs while shading
+    let mut fog = Fog::new();
+    fog.mode = option("fog").map_or(fog.mode, FogMode::parse);
+    fog.density = number("fog-density").unwrap_or(fog.density);
+    if let Some(range) = option("fog-range") {
+        match range
+            .split(',')
+            .map(|v| v.parse().ok())
+            .collect::<Option<Vec<f32>>>()
+            .as_deref()
+        {
+            Some(&[start, end]) if start < end => {
+                fog.start = start;
+                fog.end = end;
+            }
+            _ => panic!("bad --fog-range {}", range),
+        }
+    }
+    if let Some(color) = option("fog-color") {
+        fog.color = Vec3f::parse(color).unwrap_or_else(|| panic!("bad --fog-color {}", color));
+    }
+    fog.height_density = number("height-fog").unwrap_or(fog.height_density);
+    fog.per_fragment = flag("fog-per-fragment");
+
+    // --cull=none, back or front, --front-face=ccw or cw, --two-sided lights
+    // back faces with their normal flipped
+    let raster = RasterState {
+        cull: option("cull").map_or(CullMode::Back, CullMode::parse),
+        front_face: option("front-face").map_or(Winding::Ccw, Winding::parse),
+        two_sided: flag("two-sided"),
+    };
+
+    let conf = ShaderConf {
+        occlusion: true,
+        fog,
+        raster,
+        ibl: true,
+        // gltf materials are metallic-roughness
+        pbr: gltf_path.is_some(),
+        // --debug=world-normals, uv-checker, overdraw, ... draws that instead of the lit color
+        debug: option("debug").map_or(DebugView::Off, DebugView::parse),
+        tone_map,
+        exposure,
+        ..ShaderConf::new()
+    }
+    .framed(&camera);
+    let env_view = env.look_at(&lookat_m);
+    let ramp = if Path::new("./res/ramp.tga").exists() {
+        Image::from_file("./res/ramp.tga".to_string())
+    } else {
+        toon::ramp(conf.cel_bands)
+    };
 
     // println!("{:?}", lookat.mul(&lookat_i));
+    let identity = Transform::new().matrix();
     let mut shader = BasicShader {
-        conf: ShaderConf::new(),
-        light_dir,
+        conf: conf.clone(),
+        lights,
+        env: if conf.ibl { Some(env_view) } else { None },
+        camera,
         lookat_m,
         lookat_mi,
-        model: &model,
+        instance: InstanceData::new(&identity),
+        instances: &[],
+        model: &scene.models[0],
+        material: &scene.models[0].material,
+        ramp: &ramp,
         out_texture: &mut out_texture,
         z_buffer: &mut z_buffer,
         light_texture: &mut light_texture,
+        gbuffer: &mut gbuffer,
+        cache: VertexCache::new(),
+        joint_matrices: Vec::new(),
+        joint_normals: Vec::new(),
+        morph_weights: Vec::new(),
         varying_uv: Matrix::zeroed(),
         varying_xy: Matrix::zeroed(),
+        varying_view: Matrix::zeroed(),
+        varying_skin: [None; 3],
+        varying_normal: Matrix::zeroed(),
+        varying_normal_delta: Matrix::zeroed(),
+        varying_color: Matrix::zeroed(),
         vertices: [Vec3f::zeroed(); 3],
         normal_face_vec: None,
+        face: 0,
     };
 
-    for f in 0..model.num_faces() {
-        let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-        for v in 0..3 {
-            vertices[v] = shader.vertex(f, v);
-        }
-        triangle(&vertices[0], &vertices[1], &vertices[2], &mut shader);
+    scene.draw(&mut shader);
+    scene::draw_instanced(&scene.models[0], &crowd, &mut shader);
+
+    // debug views show what the shader wrote as is
+    let shaded = conf.debug == DebugView::Off;
+
+    if shaded {
+        background.draw_sky(&mut out_texture, &gbuffer, &env_view);
     }
 
-    let light_model = Model::screen_texture_model(); 
+    let light_model = Model::screen_texture_model();
 
-    let mut occl_texture = Image::new(width, height);
-    let mut light_shader = LightShader {
-        conf: ShaderConf::new(),
-        model: &light_model,
-        out_texture: &mut out_texture,
-        light_texture: &mut light_texture,
-        z_buffer: &mut z_buffer,
-        varying_uv: Matrix::zeroed(),
-        varying_xy: Matrix::zeroed(),
-        occl_texture: &mut occl_texture,
-    };
+    if shaded && conf.occlusion {
+        let ambient = Ssao::new(&conf).occlusion(&gbuffer);
+        let mut occl_texture = Image::new(width, height);
+        let mut light_shader = LightShader {
+            conf: conf.clone(),
+            model: &light_model,
+            out_texture: &mut out_texture,
+            ambient: &ambient,
+            varying_uv: Matrix::zeroed(),
+            varying_xy: Matrix::zeroed(),
+            occl_texture: &mut occl_texture,
+        };
 
-    for f in 0..light_model.num_faces() {
-        let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-        for v in 0..3 {
-            vertices[v] = light_shader.vertex(f, v);
+        for f in 0..light_model.num_faces() {
+            let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
+            for (v, vertex) in vertices.iter_mut().enumerate() {
+                *vertex = light_shader.vertex(f, v);
+            }
+            triangle(&vertices[0], &vertices[1], &vertices[2], &mut light_shader);
         }
-        triangle(&vertices[0], &vertices[1], &vertices[2], &mut light_shader);
+        occl_texture.write_to_tga("occl.tga").unwrap();
+    }
+
+    if shaded && conf.outlines {
+        toon::outlines(&mut out_texture, &gbuffer, Vec3f::zeroed(), camera.scale());
+    }
+
+    if shaded && conf.fog.enabled() && !conf.fog.per_fragment {
+        conf.fog.apply_pass(&mut out_texture, &gbuffer, &lookat_m);
+    }
+
+    if shaded && conf.lens.enabled() {
+        conf.lens.apply(&mut out_texture, &gbuffer);
+        conf.lens.focus_check(&mut z_buffer, &gbuffer);
     }
 
-    out_texture.apply_gamma(1.5);
+    let mut out_texture = if shaded {
+        let mut out_texture = post.run(out_texture, &conf);
+        background.draw(&mut out_texture, &gbuffer);
+        out_texture
+    } else {
+        debug::finish(out_texture, &gbuffer, conf.debug)
+    };
+    if conf.normal_glyphs {
+        for (model, _, model_m) in scene.visible() {
+            glyphs::draw(&mut out_texture, &gbuffer, model, &model_m, &camera);
+        }
+    }
     out_texture.write_to_tga("african_head.tga").unwrap();
     z_buffer.write_to_tga("zbuff.tga").unwrap();
     light_texture.write_to_tga("light.tga").unwrap();
-    occl_texture.write_to_tga("occl.tga").unwrap();
 }
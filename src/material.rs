@@ -0,0 +1,220 @@
+use std::{collections::HashMap, f32::consts::PI, fs, path::Path};
+
+use crate::{
+    env::EnvView,
+    la::Vec3f,
+    light::Light,
+    shader::ShaderConf,
+    tga::{Color, Image},
+};
+
+// metallic-roughness material, factors are multiplied by the maps when present
+pub struct Material {
+    pub base_color: Vec3f,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub base_color_map: Option<Image>,
+    pub metallic_map: Option<Image>,
+    pub roughness_map: Option<Image>,
+    pub ao_map: Option<Image>,
+}
+
+// material parameters resolved for a single fragment
+pub struct Surface {
+    pub albedo: Vec3f,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub ao: f32,
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Material {
+            base_color: Vec3f(1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 0.5,
+            base_color_map: None,
+            metallic_map: None,
+            roughness_map: None,
+            ao_map: None,
+        }
+    }
+
+    // newmtl entries of an mtl file by name. Kd and map_Kd give the base color,
+    // Pm and Pr of the pbr extension metallic and roughness, Ns stands in for a
+    // missing Pr. maps are read relative to the file, only tga ones
+    pub fn load_mtl(file: &Path) -> HashMap<String, Material> {
+        let contents = match fs::read_to_string(file) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("can't read {}: {}, materials skipped", file.display(), e);
+                return HashMap::new();
+            }
+        };
+        let dir = file.parent().unwrap_or_else(|| Path::new("."));
+        let mut materials = HashMap::new();
+        let mut name = String::new();
+        let mut roughness_set = false;
+        for l in contents.lines() {
+            let l = l.trim();
+            let keyword = l.split_ascii_whitespace().next().unwrap_or("");
+            let value = l[keyword.len()..].trim();
+            let floats: Vec<f32> = value
+                .split_ascii_whitespace()
+                .filter_map(|f| f.parse().ok())
+                .collect();
+            if keyword == "newmtl" {
+                name = value.to_string();
+                roughness_set = false;
+                materials.insert(name.clone(), Material::new());
+                continue;
+            }
+            let m = match materials.get_mut(&name) {
+                Some(m) => m,
+                None => continue,
+            };
+            match (keyword, floats.as_slice()) {
+                ("Kd", [r, g, b, ..]) => m.base_color = Vec3f(*r, *g, *b),
+                ("Pm", [metallic, ..]) => m.metallic = *metallic,
+                ("Pr", [roughness, ..]) => {
+                    m.roughness = *roughness;
+                    roughness_set = true;
+                }
+                // blinn-phong exponent to ggx roughness
+                ("Ns", [ns, ..]) if !roughness_set => m.roughness = (2.0 / (ns + 2.0)).sqrt(),
+                ("map_Kd", _) if value.to_lowercase().ends_with(".tga") => {
+                    let map = dir.join(value).to_string_lossy().to_string();
+                    m.base_color_map = Some(Image::from_file(map));
+                }
+                _ => {}
+            }
+        }
+        materials
+    }
+
+    // fallback is the linear color used when there is no base color map
+    pub fn surface(&self, u: f32, v: f32, fallback: Vec3f) -> Surface {
+        let albedo = match &self.base_color_map {
+            Some(map) => sample(map, u, v).to_linear(),
+            None => fallback,
+        };
+        let channel = |map: &Option<Image>| map.as_ref().map(|m| sample(m, u, v).0 as f32 / 255.0);
+        Surface {
+            albedo: albedo.mulv(&self.base_color),
+            metallic: self.metallic * channel(&self.metallic_map).unwrap_or(1.0),
+            roughness: (self.roughness * channel(&self.roughness_map).unwrap_or(1.0))
+                .clamp(0.04, 1.0),
+            ao: channel(&self.ao_map).unwrap_or(1.0),
+        }
+    }
+}
+
+fn sample(img: &Image, u: f32, v: f32) -> Color {
+    img.pixel_at(
+        ((u * img.width as f32).round() as i32).min(img.width - 1),
+        ((v * img.height as f32).round() as i32).min(img.height - 1),
+    )
+}
+
+impl Surface {
+    // cook-torrance with ggx distribution, smith-schlick geometry and fresnel-schlick,
+    // lambert for the diffuse part. everything is in linear color and view space
+    pub fn shade(
+        &self,
+        conf: &ShaderConf,
+        lights: &[Light],
+        env: Option<&EnvView>,
+        p: &Vec3f,
+        n: &Vec3f,
+        v: &Vec3f,
+    ) -> Vec3f {
+        let (diffuse, specular) = (conf.diff_light, conf.spec_light);
+        let f0 = Vec3f(0.04, 0.04, 0.04)
+            .mulf(1.0 - self.metallic)
+            .add(&self.albedo.mulf(self.metallic));
+        let a2 = (self.roughness * self.roughness).powi(2);
+        let k = (self.roughness + 1.0).powi(2) / 8.0;
+        let n_v = n.mul(v).max(1e-4);
+
+        let mut res = match env {
+            Some(env) => self.ambient(env, &f0, n, v, diffuse, specular),
+            None => Vec3f::zeroed(),
+        };
+        for light in lights.iter() {
+            // environment replaces flat ambient light
+            if diffuse && env.is_none() {
+                res = res.add(&light.ambient().mulv(&self.albedo).mulf(self.ao));
+            }
+            let (l, radiance) = match light.incident(p) {
+                Some(i) => i,
+                None => continue,
+            };
+            let n_l = n.mul(&l);
+            if n_l <= 0.0 {
+                continue;
+            }
+            let h = l.add(v).normalize();
+            let n_h = n.mul(&h).max(0.0);
+            let h_v = h.mul(v).max(0.0);
+
+            let d = a2 / (PI * (n_h * n_h * (a2 - 1.0) + 1.0).powi(2));
+            let g = (n_v / (n_v * (1.0 - k) + k)) * (n_l / (n_l * (1.0 - k) + k));
+            let f = f0.add(&Vec3f(1.0, 1.0, 1.0).sub(&f0).mulf((1.0 - h_v).powi(5)));
+
+            let mut brdf = Vec3f::zeroed();
+            if diffuse {
+                let kd = Vec3f(1.0, 1.0, 1.0).sub(&f).mulf(1.0 - self.metallic);
+                brdf = brdf.add(&kd.mulv(&self.albedo).mulf(1.0 / PI));
+            }
+            if specular {
+                brdf = brdf.add(&f.mulf(d * g / (4.0 * n_v * n_l)));
+            }
+            // light intensities are given for lambert surfaces,
+            // white surface facing a light of intensity 1 is white
+            res = res.add(&brdf.mulv(&radiance).mulf(PI * n_l));
+        }
+        res
+    }
+
+    // image based ambient, split sum with analytic approximation of the brdf integral
+    fn ambient(
+        &self,
+        env: &EnvView,
+        f0: &Vec3f,
+        n: &Vec3f,
+        v: &Vec3f,
+        diffuse: bool,
+        specular: bool,
+    ) -> Vec3f {
+        let n_v = n.mul(v).max(1e-4);
+        let fr = (1.0 - self.roughness).max(f0.0);
+        let f = f0.add(&Vec3f(fr, fr, fr).sub(f0).mulf((1.0 - n_v).powi(5)));
+
+        let mut res = Vec3f::zeroed();
+        if diffuse {
+            let kd = Vec3f(1.0, 1.0, 1.0).sub(&f).mulf(1.0 - self.metallic);
+            res = res.add(
+                &kd.mulv(&self.albedo)
+                    .mulv(&env.irradiance(n))
+                    .mulf(1.0 / PI),
+            );
+        }
+        if specular {
+            let r = n.mulf(2.0 * n_v).sub(v);
+            // Karis, "Physically Based Shading on Mobile"
+            let c0 = (-1.0, -0.0275, -0.572, 0.022);
+            let c1 = (1.0, 0.0425, 1.04, -0.04);
+            let rr = (
+                self.roughness * c0.0 + c1.0,
+                self.roughness * c0.1 + c1.1,
+                self.roughness * c0.2 + c1.2,
+                self.roughness * c0.3 + c1.3,
+            );
+            let a004 = (rr.0 * rr.0).min(2f32.powf(-9.28 * n_v)) * rr.0 + rr.1;
+            let (a, b) = (-1.04 * a004 + rr.2, 1.04 * a004 + rr.3);
+            let brdf = f0.mulf(a).add(&Vec3f(b, b, b));
+            res = res.add(&env.specular(&r, self.roughness).mulv(&brdf));
+        }
+        res.mulf(self.ao)
+    }
+}
@@ -1,28 +1,51 @@
-use std::fs;
+use std::{collections::HashMap, fmt::Write as _, fs, io, ops::Range};
 
 use crate::{
-    la::Vec3f,
+    la::{look_at, normal_matrix, Matrix, MatrixI, Vec3f},
+    material::Material,
+    morph::Morph,
+    skin::Skin,
     tga::{Color, Image},
 };
 
 pub struct Model {
     pub model: Wavefront,
+    // what the pipeline draws, built from the faces of model
+    pub mesh: Mesh,
     pub normal_map: Image,
+    // tangent space normal maps need the vertex normals, obj heads come with model space ones
+    pub tangent_normals: bool,
     pub texture: Image,
+    pub material: Material,
+    // mtl materials by name, for the groups that use them
+    pub materials: HashMap<String, Material>,
+    pub skin: Option<Skin>,
+    pub morph: Option<Morph>,
 }
 
 impl Model {
     pub fn new(wf: Wavefront, nm: Image, txt: Image) -> Self {
         Model {
+            mesh: Mesh::from_wavefront(&wf),
             model: wf,
             normal_map: nm,
+            tangent_normals: false,
             texture: txt,
+            material: Material::new(),
+            materials: HashMap::new(),
+            skin: None,
+            morph: None,
         }
     }
 
+    // no texture and no normal map, for meshes that come without images
+    pub fn untextured(wf: Wavefront) -> Self {
+        Model::new(wf, Image::new(0, 0), Image::new(0, 0))
+    }
+
     pub fn screen_texture_model() -> Self {
-        Model {
-            model: Wavefront {
+        Model::new(
+            Wavefront {
                 vertices: vec![
                     Vec3f(-1.0, -1.0, 0.0),
                     Vec3f(1.0, -1.0, 0.0),
@@ -31,38 +54,65 @@ impl Model {
                 ],
                 texture_coord: vec![[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]],
                 normals: Vec::new(),
+                normal_indices: Vec::new(),
                 faces: vec![([3, 0, 1], [3, 0, 1]), ([3, 1, 2], [3, 1, 2])],
+                colors: Vec::new(),
+                groups: Vec::new(),
+                mtllib: None,
             },
-            normal_map: Image::new(0, 0),
-            texture: Image::new(0, 0),
-        }
+            Image::new(0, 0),
+            Image::new(0, 0),
+        )
     }
 
     pub fn num_faces(&self) -> usize {
-        self.model.faces.len()
+        self.mesh.indices.len()
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.mesh.vertices.len()
+    }
+
+    // mesh vertex of a face corner
+    pub fn index(&self, iface: usize, nvert: usize) -> usize {
+        self.mesh.indices[iface][nvert] as usize
     }
 
     pub fn vertex(&self, iface: usize, nvert: usize) -> Vec3f {
-        let (vertices, _) = self.model.faces.get(iface).unwrap();
-        self.model.vertices[vertices[nvert] as usize]
+        self.mesh.vertices[self.index(iface, nvert)].position
     }
 
     pub fn texture_coords(&self, iface: usize, nvert: usize) -> [f32; 2] {
-        let (_, texture) = self.model.faces.get(iface).unwrap();
-        self.model.texture_coord[texture[nvert] as usize]
+        self.mesh.vertices[self.index(iface, nvert)].uv
+    }
+
+    pub fn has_normal_map(&self) -> bool {
+        self.normal_map.width > 0
+    }
+
+    pub fn has_texture(&self) -> bool {
+        self.texture.width > 0
     }
 
+    pub fn has_colors(&self) -> bool {
+        !self.model.colors.is_empty()
+    }
+
+    // white without a texture
     pub fn texture(&self, u: f32, v: f32) -> Color {
+        if !self.has_texture() {
+            return Color(255, 255, 255);
+        }
         self.texture.pixel_at(
-            (u * self.texture.width as f32).round() as i32,
-            (v * self.texture.height as f32).round() as i32,
+            ((u * self.texture.width as f32).round() as i32).min(self.texture.width - 1),
+            ((v * self.texture.height as f32).round() as i32).min(self.texture.height - 1),
         )
     }
 
     pub fn normal(&self, u: f32, v: f32) -> Vec3f {
         let normal = self.normal_map.pixel_at(
-            (u * self.normal_map.width as f32).round() as i32,
-            (v * self.normal_map.height as f32).round() as i32,
+            ((u * self.normal_map.width as f32).round() as i32).min(self.normal_map.width - 1),
+            ((v * self.normal_map.height as f32).round() as i32).min(self.normal_map.height - 1),
         );
         Vec3f(
             ((normal.2 as f32 / 255.0) * 2.) - 1.,
@@ -73,12 +123,127 @@ impl Model {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    pub position: Vec3f,
+    pub uv: [f32; 2],
+    // generated from the faces when the file has no normals
+    pub normal: Vec3f,
+    // linear rgb, white when the file has no colors
+    pub color: Vec3f,
+}
+
+// indexed triangles
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<[u32; 3]>,
+    // v, vt and vn index of the obj corner each vertex came from, vt and vn are -1 when missing
+    pub sources: Vec<(i32, i32, i32)>,
+}
+
+impl Mesh {
+    // obj corners with the same position, texture coordinate and normal become one vertex
+    pub fn from_wavefront(wf: &Wavefront) -> Self {
+        let generated = if wf.normal_indices.is_empty() {
+            Some(wf.generate_normals())
+        } else {
+            None
+        };
+        let (normals, normal_indices) = match &generated {
+            Some((normals, indices)) => (normals, indices),
+            None => (&wf.normals, &wf.normal_indices),
+        };
+        let mut unique: HashMap<(i32, i32, i32), u32> = HashMap::new();
+        let mut vertices = Vec::new();
+        let mut sources = Vec::new();
+        let indices = wf
+            .faces
+            .iter()
+            .enumerate()
+            .map(|(f, (v, t))| {
+                let mut face = [0; 3];
+                for c in 0..3 {
+                    let n = normal_indices.get(f).map_or(-1, |n| n[c]);
+                    face[c] = *unique.entry((v[c], t[c], n)).or_insert_with(|| {
+                        vertices.push(Vertex {
+                            position: wf.vertices[v[c] as usize],
+                            uv: if t[c] < 0 {
+                                [0.0, 0.0]
+                            } else {
+                                wf.texture_coord[t[c] as usize]
+                            },
+                            normal: if n < 0 {
+                                Vec3f::zeroed()
+                            } else {
+                                normals[n as usize]
+                            },
+                            color: wf
+                                .colors
+                                .get(v[c] as usize)
+                                .copied()
+                                .unwrap_or(Vec3f(1.0, 1.0, 1.0)),
+                        });
+                        sources.push((v[c], t[c], if generated.is_some() { -1 } else { n }));
+                        vertices.len() as u32 - 1
+                    });
+                }
+                face
+            })
+            .collect();
+        Mesh {
+            vertices,
+            indices,
+            sources,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Wavefront {
     pub vertices: Vec<Vec3f>,
     pub texture_coord: Vec<[f32; 2]>,
     pub normals: Vec<Vec3f>,
+    // vn index of every face corner, empty when the faces don't reference normals
+    pub normal_indices: Vec<[i32; 3]>,
     pub faces: Vec<([i32; 3], [i32; 3])>,
+    // linear rgb per vertex, empty when the file has none
+    pub colors: Vec<Vec3f>,
+    // faces from where each group starts up to the next one, none before the first
+    pub groups: Vec<Group>,
+    pub mtllib: Option<String>,
+}
+
+// run of faces sharing an o object, g group, usemtl material and s smoothing group
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub object: String,
+    pub name: String,
+    pub material: Option<String>,
+    // none before the first s statement, 0 when smoothing is off
+    pub smoothing: Option<u32>,
+    pub start: usize,
+}
+
+impl Group {
+    pub fn new(start: usize) -> Self {
+        Group {
+            object: String::new(),
+            name: String::new(),
+            material: None,
+            smoothing: None,
+            start,
+        }
+    }
+
+    // what the viewer lists, the group name or the object's without one
+    pub fn part(&self) -> &str {
+        if self.name.is_empty() {
+            &self.object
+        } else {
+            &self.name
+        }
+    }
 }
 
 impl Wavefront {
@@ -86,16 +251,144 @@ impl Wavefront {
         vertices: Vec<Vec3f>,
         faces: Vec<([i32; 3], [i32; 3])>,
         normals: Vec<Vec3f>,
+        normal_indices: Vec<[i32; 3]>,
         texture_coord: Vec<[f32; 2]>,
     ) -> Self {
         Wavefront {
             vertices,
             texture_coord,
             normals,
+            normal_indices,
             faces,
+            colors: Vec::new(),
+            groups: Vec::new(),
+            mtllib: None,
+        }
+    }
+
+    pub fn face_normal(&self, iface: usize) -> Vec3f {
+        let ([a, b, c], _) = self.faces[iface];
+        let (a, b, c) = (
+            self.vertices[a as usize],
+            self.vertices[b as usize],
+            self.vertices[c as usize],
+        );
+        b.sub(&a).cross(&c.sub(&a)).normalize()
+    }
+
+    // normals averaged from the faces around a vertex, weighted by area, and the
+    // normal of every face corner. faces only share them within a smoothing group
+    // and get flat ones with smoothing off, files without s are smooth all over
+    pub fn generate_normals(&self) -> (Vec<Vec3f>, Vec<[i32; 3]>) {
+        let mut slots: HashMap<(i32, i64), i32> = HashMap::new();
+        let mut normals = Vec::new();
+        let mut indices = Vec::with_capacity(self.faces.len());
+        for (faces, group) in self.runs() {
+            for f in faces {
+                let (vertices, _) = self.faces[f];
+                let smoothing = match group.and_then(|g| g.smoothing) {
+                    Some(0) => -(f as i64) - 1,
+                    Some(s) => s as i64,
+                    None => 0,
+                };
+                let [a, b, c] = vertices.map(|i| self.vertices[i as usize]);
+                let n = b.sub(&a).cross(&c.sub(&a));
+                indices.push(vertices.map(|v| {
+                    let slot = *slots.entry((v, smoothing)).or_insert_with(|| {
+                        normals.push(Vec3f::zeroed());
+                        normals.len() as i32 - 1
+                    });
+                    normals[slot as usize] = normals[slot as usize].add(&n);
+                    slot
+                }));
+            }
+        }
+        let normals = normals.iter().map(|n| n.normalize()).collect();
+        (normals, indices)
+    }
+
+    // the normals Mesh would generate written into the faces, for saving what gets drawn
+    pub fn with_generated_normals(&self) -> Self {
+        if !self.normal_indices.is_empty() {
+            return self.clone();
+        }
+        let (normals, normal_indices) = self.generate_normals();
+        Wavefront {
+            normals,
+            normal_indices,
+            ..self.clone()
+        }
+    }
+
+    // vertices moved by m, normals by its normal matrix
+    pub fn transformed(&self, m: &Matrix<4, 4>) -> Self {
+        let mi = normal_matrix(m);
+        Wavefront {
+            vertices: self.vertices.iter().map(|v| look_at(m, v)).collect(),
+            normals: self
+                .normals
+                .iter()
+                .map(|n| {
+                    let n: Vec3f = mi.mul(&n.embed::<4>(0.0)).into();
+                    n.normalize()
+                })
+                .collect(),
+            ..self.clone()
         }
     }
 
+    // consecutive faces and the group they are in, covering every face
+    pub fn runs(&self) -> Vec<(Range<usize>, Option<&Group>)> {
+        let first = self.groups.first().map_or(self.faces.len(), |g| g.start);
+        let mut runs = Vec::new();
+        if first > 0 {
+            runs.push((0..first, None));
+        }
+        for (i, g) in self.groups.iter().enumerate() {
+            let end = self.groups.get(i + 1).map_or(self.faces.len(), |n| n.start);
+            runs.push((g.start..end, Some(g)));
+        }
+        runs
+    }
+
+    // named parts in the order they first appear, for the web part list
+    #[cfg(not(feature = "local"))]
+    pub fn parts(&self) -> Vec<&str> {
+        let mut parts: Vec<&str> = Vec::new();
+        for g in self.groups.iter() {
+            if !g.part().is_empty() && !parts.contains(&g.part()) {
+                parts.push(g.part());
+            }
+        }
+        parts
+    }
+
+    // min and max corners of the axis aligned box around all vertices
+    pub fn bounds(&self) -> (Vec3f, Vec3f) {
+        let inf = f32::INFINITY;
+        self.vertices.iter().fold(
+            (Vec3f(inf, inf, inf), Vec3f(-inf, -inf, -inf)),
+            |(min, max), v| {
+                (
+                    Vec3f(min.0.min(v.0), min.1.min(v.1), min.2.min(v.2)),
+                    Vec3f(max.0.max(v.0), max.1.max(v.1), max.2.max(v.2)),
+                )
+            },
+        )
+    }
+
+    // sphere around the bounding box center through the farthest vertex
+    pub fn bounding_sphere(&self) -> (Vec3f, f32) {
+        let (min, max) = self.bounds();
+        let center = min.add(&max).mulf(0.5);
+        let radius = self
+            .vertices
+            .iter()
+            .map(|v| v.sub(&center).length())
+            .fold(0.0, f32::max);
+        (center, radius)
+    }
+
     pub fn parse_file(file: String) -> Self {
         let contents = fs::read_to_string(file).expect("Something went wrong reading the file");
         Wavefront::parse_string(contents)
@@ -108,6 +401,10 @@ impl Wavefront {
         let mut normals: Vec<Vec3f> = Vec::new();
         let mut tc: Vec<[f32; 2]> = Vec::new();
         let mut faces: Vec<([i32; 3], [i32; 3])> = Vec::new();
+        let mut normal_indices: Vec<[i32; 3]> = Vec::new();
+        let mut colors: Vec<Vec3f> = Vec::new();
+        let mut groups: Vec<Group> = Vec::new();
+        let mut mtllib: Option<String> = None;
         for l in lines {
             let lc = l.trim();
             if lc.starts_with("#") || l.is_empty() {
@@ -120,7 +417,13 @@ impl Wavefront {
                     items.next().unwrap().parse().unwrap(),
                     items.next().unwrap().parse().unwrap(),
                     items.next().unwrap().parse().unwrap(),
-                ))
+                ));
+                // v x y z r g b, srgb in [0, 1]
+                let rgb: Vec<f32> = items.filter_map(|c| c.parse().ok()).collect();
+                if let [r, g, b] = rgb[..] {
+                    let byte = |c: f32| (c * 255.0).round().clamp(0.0, 255.0) as u8;
+                    colors.push(Color(byte(b), byte(g), byte(r)).to_linear());
+                }
             }
             if lc.starts_with("vn ") {
                 let mut items = lc.split_ascii_whitespace();
@@ -140,27 +443,221 @@ impl Wavefront {
                 ])
             }
             if lc.starts_with("f ") {
+                // v, v/vt, v//vn or v/vt/vn, negative indices count back from the latest
+                let index = |i: Option<&str>, len: usize| {
+                    i.filter(|i| !i.is_empty()).map(|i| {
+                        let i = i.parse::<i32>().unwrap();
+                        if i < 0 {
+                            len as i32 + i
+                        } else {
+                            i - 1
+                        }
+                    })
+                };
                 let mut items = lc.split_ascii_whitespace();
                 items.next().unwrap(); // f
-                let mut f1 = items.next().unwrap().split("/");
-                let mut f2 = items.next().unwrap().split("/");
-                let mut f3 = items.next().unwrap().split("/");
-
-                faces.push((
-                    [
-                        f1.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f2.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f3.next().unwrap().parse::<i32>().unwrap() - 1,
-                    ],
-                    [
-                        f1.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f2.next().unwrap().parse::<i32>().unwrap() - 1,
-                        f3.next().unwrap().parse::<i32>().unwrap() - 1,
-                    ],
-                ))
+                let [c1, c2, c3] = [(); 3].map(|_| {
+                    let mut corner = items.next().unwrap().split('/');
+                    (
+                        index(corner.next(), vertices.len()).unwrap(),
+                        index(corner.next(), tc.len()).unwrap_or(-1),
+                        index(corner.next(), normals.len()),
+                    )
+                });
+                faces.push(([c1.0, c2.0, c3.0], [c1.1, c2.1, c3.1]));
+                if let (Some(n1), Some(n2), Some(n3)) = (c1.2, c2.2, c3.2) {
+                    normal_indices.push([n1, n2, n3]);
+                }
+            }
+            // every change of o, g, usemtl or s starts a run of faces
+            let keyword = lc.split_ascii_whitespace().next().unwrap_or("");
+            if matches!(keyword, "o" | "g" | "usemtl" | "s") {
+                let value = lc[keyword.len()..].trim().to_string();
+                let mut next = groups.last().cloned().unwrap_or_else(|| Group::new(0));
+                match keyword {
+                    "o" => next.object = value,
+                    "g" => next.name = value,
+                    "usemtl" => next.material = Some(value),
+                    // s off and s 0 alike
+                    _ => next.smoothing = Some(value.parse().unwrap_or(0)),
+                }
+                // runs without faces are replaced, ones like the run before are merged into it
+                if groups.last().is_some_and(|g| g.start == faces.len()) {
+                    groups.pop();
+                }
+                let before = groups.last().cloned().unwrap_or_else(|| Group::new(0));
+                next.start = before.start;
+                if next != before {
+                    next.start = faces.len();
+                    groups.push(next);
+                }
+            }
+            if let Some(name) = lc.strip_prefix("mtllib ") {
+                mtllib = Some(name.trim().to_string());
+            }
+        }
+        // partially referenced normals are as good as none
+        if normal_indices.len() != faces.len() {
+            normal_indices.clear();
+        }
+
+        // a run declared after the last face has nothing in it
+        if groups.last().is_some_and(|g| g.start == faces.len()) {
+            groups.pop();
+        }
+
+        // colors only some vertices have are dropped
+        if colors.len() != vertices.len() {
+            colors.clear();
+        }
+        Wavefront {
+            colors,
+            groups,
+            mtllib,
+            ..Wavefront::new(vertices, faces, normals, normal_indices, tc)
+        }
+    }
+
+    // obj text parse_string reads back the same, corners leave out the
+    // texture coordinate or normal they don't have
+    pub fn to_obj(&self) -> String {
+        let mut out = String::new();
+        if let Some(mtllib) = &self.mtllib {
+            writeln!(out, "mtllib {}", mtllib).unwrap();
+        }
+        for (i, Vec3f(x, y, z)) in self.vertices.iter().enumerate() {
+            write!(out, "v {} {} {}", x, y, z).unwrap();
+            if let Some(c) = self.colors.get(i) {
+                let Color(b, g, r) = Color::from_linear(c);
+                let c = |c: u8| c as f32 / 255.0;
+                write!(out, " {} {} {}", c(r), c(g), c(b)).unwrap();
             }
+            out.push('\n');
         }
+        for [u, v] in self.texture_coord.iter() {
+            writeln!(out, "vt {} {}", u, v).unwrap();
+        }
+        for Vec3f(x, y, z) in self.normals.iter() {
+            writeln!(out, "vn {} {} {}", x, y, z).unwrap();
+        }
+        let mut group = 0;
+        let mut last = Group::new(0);
+        for (f, (v, t)) in self.faces.iter().enumerate() {
+            while let Some(g) = self.groups.get(group).filter(|g| g.start <= f) {
+                if g.object != last.object {
+                    writeln!(out, "o {}", g.object).unwrap();
+                }
+                if g.name != last.name {
+                    writeln!(out, "g {}", g.name).unwrap();
+                }
+                if let Some(m) = g
+                    .material
+                    .as_ref()
+                    .filter(|m| last.material.as_ref() != Some(*m))
+                {
+                    writeln!(out, "usemtl {}", m).unwrap();
+                }
+                match g.smoothing {
+                    Some(s) if g.smoothing != last.smoothing && s == 0 => writeln!(out, "s off"),
+                    Some(s) if g.smoothing != last.smoothing => writeln!(out, "s {}", s),
+                    _ => Ok(()),
+                }
+                .unwrap();
+                last = g.clone();
+                group += 1;
+            }
+            out.push('f');
+            for c in 0..3 {
+                write!(out, " {}", v[c] + 1).unwrap();
+                match (t[c], self.normal_indices.get(f)) {
+                    (t, Some(n)) if t >= 0 => write!(out, "/{}/{}", t + 1, n[c] + 1),
+                    (_, Some(n)) => write!(out, "//{}", n[c] + 1),
+                    (t, None) if t >= 0 => write!(out, "/{}", t + 1),
+                    _ => Ok(()),
+                }
+                .unwrap();
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn write_to_obj(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_obj())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same(a: &Wavefront, b: &Wavefront) {
+        let v = |v: &Vec<Vec3f>| v.iter().map(|v| (v.0, v.1, v.2)).collect::<Vec<_>>();
+        assert_eq!(v(&a.vertices), v(&b.vertices));
+        assert_eq!(v(&a.normals), v(&b.normals));
+        assert_eq!(v(&a.colors), v(&b.colors));
+        assert_eq!(a.texture_coord, b.texture_coord);
+        assert_eq!(a.faces, b.faces);
+        assert_eq!(a.normal_indices, b.normal_indices);
+        assert_eq!(a.groups, b.groups);
+        assert_eq!(a.mtllib, b.mtllib);
+    }
+
+    const POSITIONS: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn obj_round_trip() {
+        let corners = [
+            ("", "f 1 2 3\nf 1 3 4\n"),
+            ("vt 0 0\nvt 1 0\nvt 1 1\n", "f 1/1 2/2 3/3\nf 1/1 3/3 4/2\n"),
+            (
+                "vn 0 0 1\nvn 0 0.5 0.5\n",
+                "f 1//1 2//1 3//2\nf 1//2 3//2 4//1\n",
+            ),
+            (
+                "vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\n",
+                "f 1/1/1 2/2/1 3/3/1\nf 1/1/1 3/3/1 4/2/1\n",
+            ),
+        ];
+        for (attributes, faces) in corners {
+            let text = format!(
+                "mtllib head.mtl\n{}{}o head\ng left\nusemtl skin\ns 1\n{}g right\ns off\n{}",
+                POSITIONS, attributes, faces, faces
+            );
+            let wf = Wavefront::parse_string(text);
+            assert_eq!(wf.faces.len(), 4);
+            assert_eq!(wf.groups.len(), 2);
+            let again = Wavefront::parse_string(wf.to_obj());
+            assert_same(&wf, &again);
+            assert_eq!(wf.to_obj(), again.to_obj());
+        }
+    }
+
+    #[test]
+    fn baked_normals_round_trip() {
+        let wf = Wavefront::parse_string(format!("{}f 1 2 3\nf 1 3 4\n", POSITIONS));
+        let baked = wf.with_generated_normals();
+        assert_eq!(baked.normal_indices.len(), 2);
+        let again = Wavefront::parse_string(baked.to_obj());
+        assert_same(&baked, &again);
+        let n = Mesh::from_wavefront(&again).vertices[0].normal;
+        assert_eq!((n.0, n.1, n.2), (0.0, 0.0, 1.0));
+    }
 
-        Wavefront::new(vertices, faces, normals, tc)
+    #[test]
+    fn transformed_moves_vertices_and_normals() {
+        let wf = Wavefront::parse_string(format!("{}vn 1 0 0\nf 1//1 2//1 3//1\n", POSITIONS));
+        // scaled by 2 along x and moved up by 1
+        let m = [
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let moved = wf.transformed(&m);
+        let Vec3f(x, y, z) = moved.vertices[2];
+        assert_eq!((x, y, z), (2.0, 2.0, 0.0));
+        let Vec3f(x, y, z) = moved.normals[0];
+        assert_eq!((x, y, z), (1.0, 0.0, 0.0));
     }
 }
@@ -0,0 +1,158 @@
+use crate::{
+    la::{interpolate, Vec3f},
+    model::{Mesh, Wavefront},
+    skin::span,
+};
+
+// position and normal offsets from the base shape, one per mesh vertex
+pub struct MorphTarget {
+    pub positions: Vec<Vec3f>,
+    pub normals: Vec<Vec3f>,
+}
+
+impl MorphTarget {
+    // differences to an obj with the same vertices and faces as the base, None
+    // when the topology doesn't match. normals are only compared if both have them,
+    // generated ones come from the same faces and smoothing groups on both sides
+    pub fn from_wavefront(base: &Wavefront, mesh: &Mesh, target: &Wavefront) -> Option<Self> {
+        if target.vertices.len() != base.vertices.len()
+            || target.faces.len() != base.faces.len()
+            || target
+                .faces
+                .iter()
+                .zip(base.faces.iter())
+                .any(|((a, _), (b, _))| a != b)
+        {
+            return None;
+        }
+        let positions = mesh
+            .sources
+            .iter()
+            .map(|(v, _, _)| target.vertices[*v as usize].sub(&base.vertices[*v as usize]))
+            .collect();
+        let normals = if base.normal_indices.is_empty() {
+            let (generated, indices) = target.generate_normals();
+            let mut normals = vec![Vec3f::zeroed(); mesh.vertices.len()];
+            for (face, corners) in mesh.indices.iter().zip(indices.iter()) {
+                for (i, n) in face.iter().zip(corners.iter()) {
+                    let i = *i as usize;
+                    normals[i] = generated[*n as usize].sub(&mesh.vertices[i].normal);
+                }
+            }
+            normals
+        } else {
+            let compare = target.normals.len() == base.normals.len();
+            mesh.sources
+                .iter()
+                .map(|(_, _, n)| match *n as usize {
+                    n if compare && n < base.normals.len() => {
+                        target.normals[n].sub(&base.normals[n])
+                    }
+                    _ => Vec3f::zeroed(),
+                })
+                .collect()
+        };
+        Some(MorphTarget { positions, normals })
+    }
+}
+
+// target weights keyed over time
+pub struct MorphClip {
+    pub duration: f32,
+    pub times: Vec<f32>,
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl MorphClip {
+    // every target up to full weight and back in turn, a second each
+    pub fn cycle(targets: usize) -> Self {
+        let times = (0..=targets * 2).map(|t| t as f32 * 0.5).collect();
+        let weights = (0..=targets * 2)
+            .map(|t| {
+                (0..targets)
+                    .map(|i| if t == i * 2 + 1 { 1.0 } else { 0.0 })
+                    .collect()
+            })
+            .collect();
+        MorphClip {
+            duration: targets as f32,
+            times,
+            weights,
+        }
+    }
+
+    // looping like skeletal clips
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        if self.times.is_empty() {
+            return Vec::new();
+        }
+        let time = if self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            0.0
+        };
+        let (a, b, t) = span(&self.times, time);
+        self.weights[a]
+            .iter()
+            .zip(self.weights[b].iter())
+            .map(|(a, b)| interpolate(*a, *b, t))
+            .collect()
+    }
+}
+
+pub struct Morph {
+    pub targets: Vec<MorphTarget>,
+    pub clips: Vec<MorphClip>,
+}
+
+impl Morph {
+    // the targets one after another in a single clip
+    pub fn cycle(targets: Vec<MorphTarget>) -> Self {
+        Morph {
+            clips: vec![MorphClip::cycle(targets.len())],
+            targets,
+        }
+    }
+
+    // weights of a clip at time, the fixed ones without a clip
+    pub fn weights(&self, clip: Option<usize>, time: f32, fixed: &[f32]) -> Vec<f32> {
+        match clip.and_then(|c| self.clips.get(c)) {
+            Some(clip) => clip.sample(time),
+            None => fixed.to_vec(),
+        }
+    }
+
+    // weighted position and normal offsets of a mesh vertex
+    pub fn blend(&self, index: usize, weights: &[f32]) -> (Vec3f, Vec3f) {
+        self.targets.iter().zip(weights.iter()).fold(
+            (Vec3f::zeroed(), Vec3f::zeroed()),
+            |(p, n), (target, w)| {
+                (
+                    p.add(&target.positions[index].mulf(*w)),
+                    n.add(&target.normals[index].mulf(*w)),
+                )
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_normal_deltas() {
+        let base = Wavefront::parse_string("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n".to_string());
+        let target = Wavefront::parse_string("v 0 0 0\nv 1 0 1\nv 0 1 0\nf 1 2 3\n".to_string());
+        let mesh = Mesh::from_wavefront(&base);
+        let morph = MorphTarget::from_wavefront(&base, &mesh, &target).unwrap();
+        // the target leans back around the y axis, its normal is (-1, 0, 1) normalized
+        let s = 0.5f32.sqrt();
+        for (n, v) in morph.normals.iter().zip(mesh.vertices.iter()) {
+            let Vec3f(x, y, z) = v.normal.add(n);
+            assert!((x + s).abs() < 1e-5 && y.abs() < 1e-5 && (z - s).abs() < 1e-5);
+        }
+        let Vec3f(x, y, z) = morph.positions[1];
+        assert_eq!((x, y, z), (0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,343 @@
+use std::fs;
+
+use crate::{la::Vec3f, model::Wavefront, tga::Color};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+struct Property {
+    name: String,
+    kind: String,
+    // type of the length in front of list properties
+    count: Option<String>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+pub fn parse_file(file: String) -> Wavefront {
+    let bytes = fs::read(&file).unwrap_or_else(|e| panic!("can't read {}: {}", file, e));
+    parse(&bytes).unwrap_or_else(|e| panic!("{}: {}", file, e))
+}
+
+// ascii and binary of either byte order. positions, normals, colors, texture
+// coordinates and polygons fanned out into triangles, other elements are skipped
+pub fn parse(bytes: &[u8]) -> Result<Wavefront, String> {
+    if !bytes.starts_with(b"ply") {
+        return Err("not a ply file".to_string());
+    }
+    let end = bytes
+        .windows(10)
+        .position(|w| w == b"end_header")
+        .ok_or("ply without end_header")?;
+    let body = end
+        + bytes[end..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or("ply header isn't terminated")?
+        + 1;
+    let header = String::from_utf8_lossy(&bytes[..end]);
+
+    let mut format = Format::Ascii;
+    let mut elements: Vec<Element> = Vec::new();
+    for l in header.lines() {
+        let items: Vec<&str> = l.split_ascii_whitespace().collect();
+        match items.as_slice() {
+            ["format", f, ..] => {
+                format = match *f {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    f => return Err(format!("bad ply format {}", f)),
+                }
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| format!("bad ply element count {}", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", .., name] => {
+                let (kind, count) = match items.as_slice() {
+                    ["property", "list", count, kind, _] => (kind, Some(count.to_string())),
+                    ["property", kind, _] => (kind, None),
+                    _ => return Err(format!("bad ply property {}", l)),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| format!("ply property {} before any element", name))?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        kind: kind.to_string(),
+                        count,
+                    })
+            }
+            _ => {}
+        }
+    }
+
+    let mut reader = Reader {
+        bytes,
+        pos: body,
+        format,
+        tokens: if format == Format::Ascii {
+            String::from_utf8_lossy(&bytes[body..])
+                .split_ascii_whitespace()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        }
+        .into_iter(),
+    };
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut colors = Vec::new();
+    let mut uv = Vec::new();
+    let mut faces = Vec::new();
+    for element in elements.iter() {
+        for _ in 0..element.count {
+            let mut values: Vec<(&str, Vec<f64>, bool)> = Vec::new();
+            for p in element.properties.iter() {
+                let v = match &p.count {
+                    Some(count) => {
+                        let n = reader.read(count)? as usize;
+                        (0..n)
+                            .map(|_| reader.read(&p.kind))
+                            .collect::<Result<_, _>>()?
+                    }
+                    None => vec![reader.read(&p.kind)?],
+                };
+                values.push((
+                    &p.name,
+                    v,
+                    p.kind.starts_with("float") || p.kind == "double",
+                ));
+            }
+            let get = |names: &[&str]| {
+                values
+                    .iter()
+                    .find(|(n, _, _)| names.contains(n))
+                    .and_then(|(_, v, float)| Some((*v.first()? as f32, *float)))
+            };
+            match element.name.as_str() {
+                "vertex" => {
+                    let x = |name: &str| get(&[name]).map_or(0.0, |v| v.0);
+                    vertices.push(Vec3f(x("x"), x("y"), x("z")));
+                    if let (Some(nx), Some(ny), Some(nz)) =
+                        (get(&["nx"]), get(&["ny"]), get(&["nz"]))
+                    {
+                        normals.push(Vec3f(nx.0, ny.0, nz.0));
+                    }
+                    // integer colors are bytes, float ones [0, 1]
+                    let byte = |c: (f32, bool)| {
+                        if c.1 {
+                            (c.0 * 255.0).round().clamp(0.0, 255.0) as u8
+                        } else {
+                            c.0 as u8
+                        }
+                    };
+                    if let (Some(r), Some(g), Some(b)) = (
+                        get(&["red", "r", "diffuse_red"]),
+                        get(&["green", "g", "diffuse_green"]),
+                        get(&["blue", "b", "diffuse_blue"]),
+                    ) {
+                        colors.push(Color(byte(b), byte(g), byte(r)).to_linear());
+                    }
+                    if let (Some(u), Some(v)) =
+                        (get(&["s", "u", "texture_u"]), get(&["t", "v", "texture_v"]))
+                    {
+                        uv.push([u.0, v.0]);
+                    }
+                }
+                "face" => {
+                    if let Some((_, v, _)) = values
+                        .iter()
+                        .find(|(n, _, _)| *n == "vertex_indices" || *n == "vertex_index")
+                    {
+                        for i in 1..v.len().saturating_sub(1) {
+                            let f = [v[0] as i32, v[i] as i32, v[i + 1] as i32];
+                            faces.push((f, f));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let n = vertices.len();
+    if let Some(i) = faces
+        .iter()
+        .flat_map(|(f, _)| f.iter())
+        .find(|i| **i < 0 || **i as usize >= n)
+    {
+        return Err(format!("ply face index {} past the {} vertices", i, n));
+    }
+
+    // attributes only some vertices have are dropped
+    let normal_indices = if normals.len() == n {
+        faces.iter().map(|(f, _)| *f).collect()
+    } else {
+        normals.clear();
+        Vec::new()
+    };
+    if uv.len() != n {
+        uv.clear();
+        faces.iter_mut().for_each(|(_, t)| *t = [-1; 3]);
+    }
+    if colors.len() != n {
+        colors.clear();
+    }
+    Ok(Wavefront {
+        colors,
+        ..Wavefront::new(vertices, faces, normals, normal_indices, uv)
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    format: Format,
+    tokens: std::vec::IntoIter<String>,
+}
+
+impl Reader<'_> {
+    fn read(&mut self, kind: &str) -> Result<f64, String> {
+        if self.format == Format::Ascii {
+            let t = self.tokens.next().ok_or("ply ended early")?;
+            return t.parse().map_err(|_| format!("bad ply number {}", t));
+        }
+        let size = match kind {
+            "char" | "int8" | "uchar" | "uint8" => 1,
+            "short" | "int16" | "ushort" | "uint16" => 2,
+            "int" | "int32" | "uint" | "uint32" | "float" | "float32" => 4,
+            "double" | "float64" => 8,
+            k => return Err(format!("bad ply type {}", k)),
+        };
+        let mut b = [0u8; 8];
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + size)
+            .ok_or("ply ended early")?;
+        b[..size].copy_from_slice(bytes);
+        self.pos += size;
+        if self.format == Format::BigEndian {
+            b[..size].reverse();
+        }
+        Ok(match kind {
+            "char" | "int8" => b[0] as i8 as f64,
+            "uchar" | "uint8" => b[0] as f64,
+            "short" | "int16" => i16::from_le_bytes([b[0], b[1]]) as f64,
+            "ushort" | "uint16" => u16::from_le_bytes([b[0], b[1]]) as f64,
+            "int" | "int32" => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "uint" | "uint32" => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "float" | "float32" => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            _ => f64::from_le_bytes(b),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 2
+property list uchar int vertex_indices
+element edge 1
+property list uchar int vertex_indices
+end_header
+";
+
+    // a square as a quad and an empty face, the edge list has no items at all
+    fn binary(format: &str, order: fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+        let mut v = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        let corners = [(0.0f32, 0.0f32), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+        for (x, y) in corners {
+            for c in [x, y, 0.0] {
+                v.extend(order(&c.to_le_bytes()));
+            }
+            v.extend_from_slice(&[255, 0, 0]);
+        }
+        v.push(4);
+        for i in 0..4i32 {
+            v.extend(order(&i.to_le_bytes()));
+        }
+        v.push(0);
+        v.push(0);
+        v
+    }
+
+    fn check(wf: &Wavefront) {
+        assert_eq!(wf.vertices.len(), 4);
+        assert_eq!(
+            wf.faces.iter().map(|(f, _)| *f).collect::<Vec<_>>(),
+            [[0, 1, 2], [0, 2, 3]]
+        );
+        let Vec3f(r, g, b) = wf.colors[3];
+        assert_eq!((r, g, b), (1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn ascii_ply() {
+        let text = format!(
+            "ply\nformat ascii 1.0\n{}0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 255 0 0\n0 1 0 255 0 0\n4 0 1 2 3\n0\n0\n",
+            HEADER
+        );
+        check(&parse(text.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn little_endian_ply() {
+        check(&parse(&binary("binary_little_endian", |b| b.to_vec())).unwrap());
+    }
+
+    #[test]
+    fn big_endian_ply() {
+        check(
+            &parse(&binary("binary_big_endian", |b| {
+                b.iter().rev().copied().collect()
+            }))
+            .unwrap(),
+        );
+    }
+
+    #[test]
+    fn face_index_past_the_vertices() {
+        let text = format!(
+            "ply\nformat ascii 1.0\n{}0 0 0 0 0 0\n1 0 0 0 0 0\n1 1 0 0 0 0\n0 1 0 0 0 0\n3 0 1 4\n0\n0\n",
+            HEADER
+        );
+        let err = parse(text.as_bytes()).err();
+        assert_eq!(err.unwrap(), "ply face index 4 past the 4 vertices");
+    }
+
+    #[test]
+    fn malformed_ply() {
+        let err = |s: &str| parse(s.as_bytes()).err().unwrap();
+        assert_eq!(
+            err("ply\nformat ascii 1.0\nproperty float x\nelement vertex 1\nend_header\n"),
+            "ply property x before any element"
+        );
+        assert_eq!(
+            err("ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nend_header\n1\n"),
+            "ply ended early"
+        );
+    }
+}
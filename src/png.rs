@@ -0,0 +1,535 @@
+use crate::tga::{Color, Image};
+
+// lsb first, the way deflate packs its bits
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl Bits<'_> {
+    fn next(&mut self) -> Result<u32, String> {
+        let b = *self
+            .data
+            .get(self.pos)
+            .ok_or("deflate stream ended early")?;
+        let v = (b as u32 >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(v)
+    }
+
+    fn read(&mut self, n: u32) -> Result<u32, String> {
+        (0..n).try_fold(0, |v, i| Ok(v | self.next()? << i))
+    }
+
+    fn align(&mut self) {
+        if self.bit > 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// canonical huffman code as code counts per length and symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for l in lengths.iter() {
+            counts[*l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for l in 1..15 {
+            offsets[l + 1] = offsets[l] + counts[l];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (s, l) in lengths.iter().enumerate() {
+            if *l > 0 {
+                symbols[offsets[*l as usize] as usize] = s as u16;
+                offsets[*l as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= bits.next()? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("bad huffman code in deflate stream".to_string())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// zlib wrapped deflate, the checksum isn't verified
+pub fn inflate(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut bits = Bits {
+        data,
+        pos: 2,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = bits.next()? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let p = bits.pos;
+                let len = match data.get(p..p + 2) {
+                    Some(&[a, b]) => u16::from_le_bytes([a, b]) as usize,
+                    _ => return Err("stored block without a length".to_string()),
+                };
+                let stored = data
+                    .get(p + 4..p + 4 + len)
+                    .ok_or_else(|| format!("stored block of {} bytes past the end", len))?;
+                out.extend_from_slice(stored);
+                bits.pos = p + 4 + len;
+            }
+            1 => {
+                let mut lengths = [8u8; 288];
+                lengths[144..256].iter_mut().for_each(|l| *l = 9);
+                lengths[256..280].iter_mut().for_each(|l| *l = 7);
+                let lit = Huffman::new(&lengths);
+                let dist = Huffman::new(&[5; 30]);
+                block(&mut bits, &mut out, &lit, &dist)?;
+            }
+            2 => {
+                let hlit = bits.read(5)? as usize + 257;
+                let hdist = bits.read(5)? as usize + 1;
+                let hclen = bits.read(4)? as usize + 4;
+                let order = [
+                    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+                ];
+                let mut code_lengths = [0u8; 19];
+                for i in order.iter().take(hclen) {
+                    code_lengths[*i] = bits.read(3)? as u8;
+                }
+                let code = Huffman::new(&code_lengths);
+                let mut lengths = Vec::with_capacity(hlit + hdist);
+                while lengths.len() < hlit + hdist {
+                    match code.decode(&mut bits)? {
+                        s @ 0..=15 => lengths.push(s as u8),
+                        16 => {
+                            let prev = *lengths.last().ok_or("repeat without a length")?;
+                            let n = 3 + bits.read(2)?;
+                            lengths.extend((0..n).map(|_| prev));
+                        }
+                        17 => {
+                            let n = 3 + bits.read(3)?;
+                            lengths.extend((0..n).map(|_| 0));
+                        }
+                        _ => {
+                            let n = 11 + bits.read(7)?;
+                            lengths.extend((0..n).map(|_| 0));
+                        }
+                    }
+                }
+                if lengths.len() > hlit + hdist {
+                    return Err("code length repeat past the end of the lengths".to_string());
+                }
+                let lit = Huffman::new(&lengths[..hlit]);
+                let dist = Huffman::new(&lengths[hlit..]);
+                block(&mut bits, &mut out, &lit, &dist)?;
+            }
+            _ => return Err("bad deflate block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn block(bits: &mut Bits, out: &mut Vec<u8>, lit: &Huffman, dist: &Huffman) -> Result<(), String> {
+    loop {
+        let s = lit.decode(bits)? as usize;
+        if s < 256 {
+            out.push(s as u8);
+        } else if s == 256 {
+            return Ok(());
+        } else {
+            let s = s - 257;
+            if s >= LENGTH_BASE.len() {
+                return Err(format!("bad length symbol {}", s + 257));
+            }
+            let len = LENGTH_BASE[s] as usize + bits.read(LENGTH_EXTRA[s] as u32)? as usize;
+            let d = dist.decode(bits)? as usize;
+            if d >= DIST_BASE.len() {
+                return Err(format!("bad distance symbol {}", d));
+            }
+            let d = DIST_BASE[d] as usize + bits.read(DIST_EXTRA[d] as u32)? as usize;
+            if d > out.len() {
+                return Err(format!("distance {} past the start of the output", d));
+            }
+            let start = out.len() - d;
+            // copies can overlap what they are producing
+            for i in 0..len {
+                out.push(out[start + i]);
+            }
+        }
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// non interlaced gray, rgb, palette, gray alpha and rgba of any bit depth,
+// alpha is dropped. rows end up bottom first like the textures from tga files
+pub fn decode(v: &[u8]) -> Result<Image, String> {
+    if !v.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Err("not a png".to_string());
+    }
+    let mut pos = 8;
+    let (mut width, mut height, mut depth, mut color) = (0, 0, 0, 0);
+    let mut palette: &[u8] = &[];
+    let mut idat = Vec::new();
+    while pos + 8 <= v.len() {
+        let len = u32::from_be_bytes([v[pos], v[pos + 1], v[pos + 2], v[pos + 3]]) as usize;
+        let kind = &v[pos + 4..pos + 8];
+        let body = v.get(pos + 8..pos + 8 + len).ok_or_else(|| {
+            format!(
+                "{} chunk of {} bytes past the end",
+                String::from_utf8_lossy(kind),
+                len
+            )
+        })?;
+        match kind {
+            b"IHDR" => {
+                if body.len() < 13 {
+                    return Err("short IHDR chunk".to_string());
+                }
+                width = u32::from_be_bytes([body[0], body[1], body[2], body[3]]) as usize;
+                height = u32::from_be_bytes([body[4], body[5], body[6], body[7]]) as usize;
+                depth = body[8] as usize;
+                color = body[9];
+                if body[12] != 0 {
+                    return Err("interlaced png isn't supported".to_string());
+                }
+            }
+            b"PLTE" => palette = body,
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len;
+    }
+
+    let channels = match color {
+        0 | 3 => 1,
+        2 => 3,
+        4 => 2,
+        6 => 4,
+        _ => return Err(format!("bad png color type {}", color)),
+    };
+    if ![1, 2, 4, 8, 16].contains(&depth) {
+        return Err(format!("bad png bit depth {}", depth));
+    }
+    let bpp = (channels * depth / 8).max(1);
+    let stride = (width * channels * depth).div_ceil(8);
+    let raw = inflate(&idat)?;
+    if raw.len() < (stride + 1) * height {
+        return Err(format!(
+            "{} bytes of image data for {} rows of {}",
+            raw.len(),
+            height,
+            stride + 1
+        ));
+    }
+    let mut rows = vec![0u8; stride * height];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let src = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        for x in 0..stride {
+            let a = if x >= bpp {
+                rows[y * stride + x - bpp]
+            } else {
+                0
+            };
+            let b = if y > 0 { rows[(y - 1) * stride + x] } else { 0 };
+            let c = if x >= bpp && y > 0 {
+                rows[(y - 1) * stride + x - bpp]
+            } else {
+                0
+            };
+            rows[y * stride + x] = src[x].wrapping_add(match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("bad png filter {}", filter)),
+            });
+        }
+    }
+
+    // most significant byte of 16 bit samples, low depths scaled up unless they index the palette
+    let sample = |row: &[u8], i: usize| -> u8 {
+        match depth {
+            16 => row[i * 2],
+            8 => row[i],
+            _ => {
+                let bit = i * depth;
+                let s = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8;
+                if color == 3 {
+                    s
+                } else {
+                    (s as u32 * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    };
+    let mut img = Image::new(width as i32, height as i32);
+    for y in 0..height {
+        let row = &rows[y * stride..(y + 1) * stride];
+        for x in 0..width {
+            let s = |c: usize| sample(row, x * channels + c);
+            let (r, g, b) = match color {
+                0 | 4 => (s(0), s(0), s(0)),
+                3 => match palette.get(s(0) as usize * 3..s(0) as usize * 3 + 3) {
+                    Some(&[r, g, b]) => (r, g, b),
+                    _ => {
+                        return Err(format!(
+                            "palette index {} past the {} colors",
+                            s(0),
+                            palette.len() / 3
+                        ))
+                    }
+                },
+                _ => (s(0), s(1), s(2)),
+            };
+            img.set_pixel(x as i32, (height - 1 - y) as i32, Color(b, g, r));
+        }
+    }
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // zlib stream of stored blocks, the adler32 isn't checked
+    fn stored(data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0x78, 0x01];
+        let blocks: Vec<&[u8]> = data.chunks(0xffff).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            let len = block.len() as u16;
+            out.push((i + 1 == blocks.len()) as u8);
+            out.extend_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(&(!len).to_le_bytes());
+            out.extend_from_slice(block);
+        }
+        out.extend_from_slice(&[0; 4]);
+        out
+    }
+
+    fn chunk(out: &mut Vec<u8>, kind: &[u8], body: &[u8]) {
+        out.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out.extend_from_slice(&[0; 4]);
+    }
+
+    // unfiltered rows of packed samples, every row filtered the same way
+    fn png(
+        width: u32,
+        color: u8,
+        depth: u8,
+        palette: &[u8],
+        rows: &[Vec<u8>],
+        filter: u8,
+    ) -> Vec<u8> {
+        let channels = match color {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        };
+        let bpp = (channels * depth as usize / 8).max(1);
+        let mut raw = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            raw.push(filter);
+            for x in 0..row.len() {
+                let a = if x >= bpp { row[x - bpp] } else { 0 };
+                let b = if y > 0 { rows[y - 1][x] } else { 0 };
+                let c = if x >= bpp && y > 0 {
+                    rows[y - 1][x - bpp]
+                } else {
+                    0
+                };
+                raw.push(row[x].wrapping_sub(match filter {
+                    0 => 0,
+                    1 => a,
+                    2 => b,
+                    3 => ((a as u16 + b as u16) / 2) as u8,
+                    _ => paeth(a, b, c),
+                }));
+            }
+        }
+        let mut ihdr = width.to_be_bytes().to_vec();
+        ihdr.extend_from_slice(&(rows.len() as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[depth, color, 0, 0, 0]);
+        let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut out, b"IHDR", &ihdr);
+        if !palette.is_empty() {
+            chunk(&mut out, b"PLTE", palette);
+        }
+        chunk(&mut out, b"IDAT", &stored(&raw));
+        chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    // rgb of the pixel at x and y counting rows from the top like the file does
+    fn rgb(img: &Image, x: i32, y: i32) -> (u8, u8, u8) {
+        let Color(b, g, r) = img.pixel_at(x, img.height - 1 - y);
+        (r, g, b)
+    }
+
+    #[test]
+    fn stored_stream() {
+        let data: Vec<u8> = (0..70000).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(inflate(&stored(&data)).unwrap(), data);
+    }
+
+    #[test]
+    fn fixed_stream() {
+        let z = [
+            120, 218, 75, 76, 42, 74, 76, 78, 76, 73, 4, 82, 10, 137, 216, 217, 0, 238, 40, 13, 61,
+        ];
+        assert_eq!(inflate(&z).unwrap(), b"abracadabra abracadabra abracadabra");
+    }
+
+    #[test]
+    fn dynamic_stream() {
+        let z = [
+            120, 218, 21, 201, 65, 10, 0, 48, 8, 196, 192, 183, 46, 24, 168, 23, 133, 186, 255,
+            167, 150, 220, 38, 28, 76, 118, 134, 32, 145, 46, 109, 190, 65, 12, 218, 138, 206, 249,
+            176, 67, 19, 235, 198, 165, 194, 15, 233, 208, 24, 149,
+        ];
+        assert_eq!((z[2] >> 1) & 3, 2);
+        assert_eq!(
+            inflate(&z).unwrap(),
+            b"eheteioidaeeieaareoteheteeedseaeaeneoiseteeoteasdeedtetnanet"
+        );
+        assert!(inflate(&z[..30]).is_err());
+    }
+
+    #[test]
+    fn distance_past_the_start() {
+        // fixed block with a length 3 match at distance 1 as its first symbol
+        let z = [0x78, 0x01, 0x03, 0x02, 0x00];
+        assert!(inflate(&z).unwrap_err().contains("past the start"));
+    }
+
+    #[test]
+    fn filters() {
+        let pixel = |x: u8, y: u8| [x * 60 + y * 7, 200 - x * 13 * y, x * y * 37];
+        let rows: Vec<Vec<u8>> = (0..3)
+            .map(|y| (0..4).flat_map(|x| pixel(x, y)).collect())
+            .collect();
+        for filter in 0..5 {
+            let img = decode(&png(4, 2, 8, &[], &rows, filter)).unwrap();
+            assert_eq!((img.width, img.height), (4, 3));
+            for y in 0..3 {
+                for x in 0..4 {
+                    let [r, g, b] = pixel(x, y);
+                    assert_eq!(
+                        rgb(&img, x as i32, y as i32),
+                        (r, g, b),
+                        "filter {}",
+                        filter
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn color_types() {
+        let gray = decode(&png(2, 0, 8, &[], &[vec![10, 200], vec![0, 255]], 1)).unwrap();
+        assert_eq!(rgb(&gray, 1, 0), (200, 200, 200));
+        assert_eq!(rgb(&gray, 0, 1), (0, 0, 0));
+
+        let gray16 = decode(&png(1, 0, 16, &[], &[vec![0x12, 0x34]], 0)).unwrap();
+        assert_eq!(rgb(&gray16, 0, 0), (0x12, 0x12, 0x12));
+
+        // low depths are scaled up to 8 bits
+        let bits = decode(&png(4, 0, 1, &[], &[vec![0b1010_0000]], 0)).unwrap();
+        assert_eq!(rgb(&bits, 0, 0), (255, 255, 255));
+        assert_eq!(rgb(&bits, 1, 0), (0, 0, 0));
+
+        let rgb16 = decode(&png(1, 2, 16, &[], &[vec![1, 0, 2, 0, 3, 0]], 2)).unwrap();
+        assert_eq!(rgb(&rgb16, 0, 0), (1, 2, 3));
+
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let indexed = decode(&png(3, 3, 2, &palette, &[vec![0b0001_1000]], 0)).unwrap();
+        assert_eq!(rgb(&indexed, 0, 0), (255, 0, 0));
+        assert_eq!(rgb(&indexed, 1, 0), (0, 255, 0));
+        assert_eq!(rgb(&indexed, 2, 0), (0, 0, 255));
+
+        let gray_alpha = decode(&png(1, 4, 8, &[], &[vec![90, 0]], 3)).unwrap();
+        assert_eq!(rgb(&gray_alpha, 0, 0), (90, 90, 90));
+
+        let rgba = decode(&png(2, 6, 8, &[], &[vec![1, 2, 3, 4, 5, 6, 7, 8]], 4)).unwrap();
+        assert_eq!(rgb(&rgba, 1, 0), (5, 6, 7));
+    }
+
+    #[test]
+    fn bad_files() {
+        let palette = [255, 0, 0];
+        let err = decode(&png(1, 3, 8, &palette, &[vec![1]], 0))
+            .err()
+            .unwrap();
+        assert!(err.contains("palette index 1"), "{}", err);
+
+        let mut v = png(1, 0, 8, &[], &[vec![1]], 0);
+        v.truncate(45);
+        let err = decode(&v).err().unwrap();
+        assert!(err.contains("past the end"), "{}", err);
+
+        assert!(decode(b"GIF89a").is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{
+    hdr::{HdrImage, ToneMap},
+    la::{interpolatev, smoothstep, Vec3f},
+    shader::ShaderConf,
+    tga::Image,
+};
+
+const BLOOM_LEVELS: usize = 5;
+const GAUSS: [f32; 5] = [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+
+#[derive(Debug, Clone, Copy)]
+pub enum Pass {
+    // everything brighter than the threshold is blurred and added back on top
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+    },
+    // exposure and the curve come from the shader conf
+    ToneMap,
+    // contrast is around middle grey, tint multiplies the result
+    ColorGrade {
+        saturation: f32,
+        contrast: f32,
+        tint: Vec3f,
+    },
+    // radius is where darkening starts, 1.0 is the corner
+    Vignette {
+        strength: f32,
+        radius: f32,
+    },
+    // power curve on each channel, runs where it is in the chain
+    Gamma(f32),
+}
+
+impl Pass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::Bloom { .. } => "Bloom",
+            Pass::ToneMap => "Tone mapping",
+            Pass::ColorGrade { .. } => "Color grading",
+            Pass::Vignette { .. } => "Vignette",
+            Pass::Gamma(_) => "Gamma",
+        }
+    }
+
+    fn apply(&self, img: &mut HdrImage, conf: &ShaderConf) {
+        match *self {
+            Pass::Bloom {
+                threshold,
+                intensity,
+            } => bloom(img, threshold, intensity),
+            Pass::ToneMap => img.tone_map(conf.exposure, conf.tone_map),
+            Pass::ColorGrade {
+                saturation,
+                contrast,
+                tint,
+            } => {
+                for c in img.data.iter_mut() {
+                    let luma = c.mul(&Vec3f(0.2126, 0.7152, 0.0722));
+                    let s = interpolatev(&Vec3f(luma, luma, luma), c, saturation);
+                    let grade = |v: f32| 0.18 * (v.max(0.0) / 0.18).powf(contrast);
+                    *c = Vec3f(grade(s.0), grade(s.1), grade(s.2)).mulv(&tint);
+                }
+            }
+            Pass::Vignette { strength, radius } => {
+                for y in 0..img.height {
+                    for x in 0..img.width {
+                        let dx = x as f32 / (img.width - 1) as f32 - 0.5;
+                        let dy = y as f32 / (img.height - 1) as f32 - 0.5;
+                        let d = (dx * dx + dy * dy).sqrt() * 2f32.sqrt();
+                        let k = 1.0 - strength * smoothstep(radius, 1.0, d);
+                        let c = img.pixel_at(x, y);
+                        img.set_pixel(x, y, c.mulf(k));
+                    }
+                }
+            }
+            Pass::Gamma(gamma) => {
+                for c in img.data.iter_mut() {
+                    let curve = |v: f32| v.max(0.0).powf(gamma);
+                    *c = Vec3f(curve(c.0), curve(c.1), curve(c.2));
+                }
+            }
+        }
+    }
+}
+
+// ordered list of passes run over the hdr framebuffer, each can be switched off
+pub struct PostChain {
+    pub passes: Vec<(Pass, bool)>,
+}
+
+impl PostChain {
+    pub fn new() -> Self {
+        PostChain {
+            passes: vec![
+                (
+                    Pass::Bloom {
+                        threshold: 1.0,
+                        intensity: 0.5,
+                    },
+                    true,
+                ),
+                (Pass::ToneMap, true),
+                (
+                    Pass::ColorGrade {
+                        saturation: 1.15,
+                        contrast: 1.1,
+                        tint: Vec3f(1.0, 0.98, 0.95),
+                    },
+                    false,
+                ),
+                (
+                    Pass::Vignette {
+                        strength: 0.5,
+                        radius: 0.5,
+                    },
+                    false,
+                ),
+                (Pass::Gamma(1.0), false),
+            ],
+        }
+    }
+
+    // comma separated pass names in the order to run them, e.g.
+    // "bloom,tone-mapping,gamma:1.2", the passes left out are switched off
+    pub fn parse(s: &str) -> Self {
+        let mut rest = PostChain::new().passes;
+        let mut passes = Vec::new();
+        for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            let (name, value) = match item.split_once(':') {
+                Some((name, value)) => (name, Some(value)),
+                None => (item, None),
+            };
+            let i = rest
+                .iter()
+                .position(|(p, _)| p.name().to_lowercase().replace(' ', "-") == name)
+                .unwrap_or_else(|| panic!("bad post pass {}", item));
+            let (mut pass, _) = rest.remove(i);
+            match (&mut pass, value) {
+                (Pass::Gamma(gamma), Some(v)) => {
+                    *gamma = v.parse().unwrap_or_else(|_| panic!("bad gamma {}", v))
+                }
+                (_, Some(_)) => panic!("{} takes no value", name),
+                _ => {}
+            }
+            passes.push((pass, true));
+        }
+        passes.extend(rest.into_iter().map(|(p, _)| (p, false)));
+        PostChain { passes }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn toggle(&mut self, i: usize) {
+        if let Some(p) = self.passes.get_mut(i) {
+            p.1 = !p.1;
+        }
+    }
+
+    // swaps the pass with the one running before it
+    #[cfg(not(feature = "local"))]
+    pub fn move_up(&mut self, i: usize) {
+        if i > 0 && i < self.passes.len() {
+            self.passes.swap(i - 1, i);
+        }
+    }
+
+    // without the tone mapping pass colors are just clipped
+    pub fn run(&self, mut img: HdrImage, conf: &ShaderConf) -> Image {
+        for (pass, _) in self.passes.iter().filter(|(_, on)| *on) {
+            pass.apply(&mut img, conf);
+        }
+        img.to_image(0.0, ToneMap::Clamp)
+    }
+}
+
+fn bloom(img: &mut HdrImage, threshold: f32, intensity: f32) {
+    // bright pass at half resolution, soft knee hides where the threshold is
+    let knee = threshold * 0.5;
+    let mut bright = img.downsample((img.width / 2).max(1), (img.height / 2).max(1));
+    for c in bright.data.iter_mut() {
+        let b = c.0.max(c.1).max(c.2);
+        let soft = (b - threshold + knee).clamp(0.0, 2.0 * knee);
+        let soft = soft * soft / (4.0 * knee + 1e-5);
+        *c = c.mulf(soft.max(b - threshold) / b.max(1e-5));
+    }
+
+    // every level is half the size of the previous one and blurred again
+    let mut levels = vec![blur(&bright)];
+    while levels.len() < BLOOM_LEVELS {
+        let last = levels.last().unwrap();
+        if last.width < 8 || last.height < 8 {
+            break;
+        }
+        let next = last.downsample(last.width / 2, last.height / 2);
+        levels.push(blur(&next));
+    }
+    let count = levels.len();
+
+    // walk back up adding the wider glow of the smaller levels
+    let mut glow = levels.pop().unwrap();
+    while let Some(level) = levels.pop() {
+        let up = resize(&glow, level.width, level.height);
+        glow = HdrImage {
+            data: level
+                .data
+                .iter()
+                .zip(up.data.iter())
+                .map(|(a, b)| a.add(b))
+                .collect(),
+            ..level
+        };
+    }
+
+    let glow = resize(&glow, img.width, img.height);
+    let k = intensity / count as f32;
+    for (c, g) in img.data.iter_mut().zip(glow.data.iter()) {
+        *c = c.add(&g.mulf(k));
+    }
+}
+
+// separable 5 tap gaussian, edges are clamped
+fn blur(img: &HdrImage) -> HdrImage {
+    let pass = |src: &HdrImage, dx: i32, dy: i32| {
+        let mut res = HdrImage::new(src.width, src.height);
+        for y in 0..src.height {
+            for x in 0..src.width {
+                let mut total = Vec3f::zeroed();
+                for (i, w) in GAUSS.iter().enumerate() {
+                    let o = i as i32 - 2;
+                    let c = src.pixel_at(
+                        (x + o * dx).clamp(0, src.width - 1),
+                        (y + o * dy).clamp(0, src.height - 1),
+                    );
+                    total = total.add(&c.mulf(*w));
+                }
+                res.set_pixel(x, y, total);
+            }
+        }
+        res
+    };
+    pass(&pass(img, 1, 0), 0, 1)
+}
+
+// bilinear, unlike HdrImage::sample nothing wraps around
+fn resize(img: &HdrImage, width: i32, height: i32) -> HdrImage {
+    let mut res = HdrImage::new(width, height);
+    for y in 0..height {
+        let sy = ((y as f32 + 0.5) * img.height as f32 / height as f32 - 0.5)
+            .clamp(0.0, (img.height - 1) as f32);
+        let y0 = sy.floor() as i32;
+        let y1 = (y0 + 1).min(img.height - 1);
+        for x in 0..width {
+            let sx = ((x as f32 + 0.5) * img.width as f32 / width as f32 - 0.5)
+                .clamp(0.0, (img.width - 1) as f32);
+            let x0 = sx.floor() as i32;
+            let x1 = (x0 + 1).min(img.width - 1);
+            let tx = sx - x0 as f32;
+            let top = interpolatev(&img.pixel_at(x0, y0), &img.pixel_at(x1, y0), tx);
+            let bottom = interpolatev(&img.pixel_at(x0, y1), &img.pixel_at(x1, y1), tx);
+            res.set_pixel(x, y, interpolatev(&top, &bottom, sy - y0 as f32));
+        }
+    }
+    res
+}
@@ -0,0 +1,422 @@
+use crate::{
+    la::{look_at, normal_matrix, Matrix, MatrixI, Rng, Vec3f},
+    material::Material,
+    model::Model,
+    shader::{triangle, BasicShader, Shader},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vec3f,
+    // euler angles in radians, applied around x, then y, then z
+    pub rotation: Vec3f,
+    pub scale: Vec3f,
+}
+
+impl Transform {
+    pub fn new() -> Self {
+        Transform {
+            translation: Vec3f::zeroed(),
+            rotation: Vec3f::zeroed(),
+            scale: Vec3f(1.0, 1.0, 1.0),
+        }
+    }
+
+    // scale, rotate, then translate
+    pub fn matrix(&self) -> Matrix<4, 4> {
+        let (sx, cx) = self.rotation.0.sin_cos();
+        let (sy, cy) = self.rotation.1.sin_cos();
+        let (sz, cz) = self.rotation.2.sin_cos();
+        let rx = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, cx, -sx, 0.0],
+            [0.0, sx, cx, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let ry = [
+            [cy, 0.0, sy, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [-sy, 0.0, cy, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let rz = [
+            [cz, -sz, 0.0, 0.0],
+            [sz, cz, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let Vec3f(x, y, z) = self.scale;
+        let s = [
+            [x, 0.0, 0.0, 0.0],
+            [0.0, y, 0.0, 0.0],
+            [0.0, 0.0, z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        let Vec3f(x, y, z) = self.translation;
+        let t = [
+            [1.0, 0.0, 0.0, x],
+            [0.0, 1.0, 0.0, y],
+            [0.0, 0.0, 1.0, z],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        t.mul(&rz.mul(&ry.mul(&rx.mul(&s))))
+    }
+}
+
+// placement of one of the scene models, the model's own material is used
+// unless the instance has its own. transform is relative to the parent instance,
+// hiding a parent hides everything under it. instances without a model only group
+pub struct Instance {
+    pub model: Option<usize>,
+    pub transform: Transform,
+    pub material: Option<Material>,
+    pub visible: bool,
+    pub parent: Option<usize>,
+    // clip of the model's skin and the time it's sampled at, bind pose without a clip
+    pub clip: Option<usize>,
+    pub time: f32,
+    // morph clip sampled at the same time, fixed target weights without one
+    pub morph_clip: Option<usize>,
+    pub weights: Vec<f32>,
+    // parts of the model left out, by group or object name
+    pub hidden: Vec<String>,
+}
+
+impl Instance {
+    pub fn new(model: usize) -> Self {
+        Instance {
+            model: Some(model),
+            transform: Transform::new(),
+            material: None,
+            visible: true,
+            parent: None,
+            clip: None,
+            time: 0.0,
+            morph_clip: None,
+            weights: Vec::new(),
+            hidden: Vec::new(),
+        }
+    }
+
+    pub fn group() -> Self {
+        Instance {
+            model: None,
+            ..Instance::new(0)
+        }
+    }
+}
+
+// per-instance attributes of an instanced draw, the mesh itself is shared
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceData {
+    pub model_m: Matrix<4, 4>,
+    // normal matrix of model_m
+    pub model_mi: Matrix<4, 4>,
+    // multiplies the albedo, linear
+    pub tint: Vec3f,
+    pub uv_offset: (f32, f32),
+}
+
+impl InstanceData {
+    pub fn new(model_m: &Matrix<4, 4>) -> Self {
+        InstanceData {
+            model_m: *model_m,
+            model_mi: normal_matrix(model_m),
+            tint: Vec3f(1.0, 1.0, 1.0),
+            uv_offset: (0.0, 0.0),
+        }
+    }
+
+    // texture coordinates shifted by the offset and wrapped back into the texture
+    pub fn uv(&self, u: f32, v: f32) -> (f32, f32) {
+        let wrap = |t: f32| {
+            if (0.0..=1.0).contains(&t) {
+                t
+            } else {
+                t.rem_euclid(1.0)
+            }
+        };
+        (wrap(u + self.uv_offset.0), wrap(v + self.uv_offset.1))
+    }
+}
+
+// rows of five small heads behind the scene with random tints and headings
+pub fn crowd(count: usize) -> Vec<InstanceData> {
+    let mut rng = Rng::new(7);
+    (0..count)
+        .map(|i| {
+            let transform = Transform {
+                translation: Vec3f((i % 5) as f32 - 2.0, -0.6, -1.5 - (i / 5) as f32),
+                rotation: Vec3f(0.0, rng.next_f32() - 0.5, 0.0),
+                scale: Vec3f(0.35, 0.35, 0.35),
+            };
+            InstanceData {
+                tint: Vec3f(
+                    0.5 + 0.5 * rng.next_f32(),
+                    0.5 + 0.5 * rng.next_f32(),
+                    0.5 + 0.5 * rng.next_f32(),
+                ),
+                ..InstanceData::new(&transform.matrix())
+            }
+        })
+        .collect()
+}
+
+// one model drawn once per buffer entry, the vertex stage gets the instance index
+pub fn draw_instanced<'a>(
+    model: &'a Model,
+    buffer: &'a [InstanceData],
+    shader: &mut BasicShader<'a>,
+) {
+    shader.set_instances(model, &model.material, buffer);
+    for i in 0..buffer.len() {
+        for f in 0..model.num_faces() {
+            let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
+            for (v, vertex) in vertices.iter_mut().enumerate() {
+                *vertex = shader.vertex_instanced(i, f, v);
+            }
+            triangle(&vertices[0], &vertices[1], &vertices[2], shader);
+        }
+    }
+}
+
+// everything drawn into the same depth buffer with the same camera
+pub struct Scene {
+    pub models: Vec<Model>,
+    pub instances: Vec<Instance>,
+}
+
+impl Scene {
+    // the model with one instance at the origin
+    pub fn single(model: Model) -> Self {
+        Scene {
+            models: vec![model],
+            instances: vec![Instance::new(0)],
+        }
+    }
+
+    // the instance and its parents up to the root
+    fn ancestry(&self, i: usize) -> impl Iterator<Item = &Instance> {
+        let mut next = Some(i);
+        // bounded so a parent cycle can't hang the renderer
+        (0..self.instances.len()).map_while(move |_| {
+            let instance = &self.instances[next?];
+            next = instance.parent;
+            Some(instance)
+        })
+    }
+
+    // local transforms composed from the root down
+    pub fn world_matrix(&self, i: usize) -> Matrix<4, 4> {
+        self.ancestry(i)
+            .fold(Transform::new().matrix(), |m, instance| {
+                instance.transform.matrix().mul(&m)
+            })
+    }
+
+    // visible instances with their world matrices
+    pub fn visible(&self) -> impl Iterator<Item = (&Model, &Instance, Matrix<4, 4>)> {
+        (0..self.instances.len())
+            .filter(move |i| self.ancestry(*i).all(|instance| instance.visible))
+            .filter_map(move |i| {
+                let instance = &self.instances[i];
+                let model = &self.models[instance.model?];
+                Some((model, instance, self.world_matrix(i)))
+            })
+    }
+
+    // sphere around everything visible in the bind pose, world space, and the
+    // first model drawn instanced over the crowd buffer
+    pub fn bounding_sphere_with(&self, crowd: &[InstanceData]) -> (Vec3f, f32) {
+        let crowd = crowd
+            .iter()
+            .filter_map(|data| Some((self.models.first()?, data.model_m)));
+        let spheres: Vec<(Vec3f, f32)> = self
+            .visible()
+            .map(|(model, _, model_m)| (model, model_m))
+            .chain(crowd)
+            .filter(|(model, _)| model.num_vertices() > 0)
+            .map(|(model, model_m)| {
+                let (center, radius) = model.model.bounding_sphere();
+                // largest of the axis scales
+                let scale = (0..3)
+                    .map(|j| Vec3f(model_m[0][j], model_m[1][j], model_m[2][j]).length())
+                    .fold(0.0, f32::max);
+                (look_at(&model_m, &center), radius * scale)
+            })
+            .collect();
+        if spheres.is_empty() {
+            return (Vec3f::zeroed(), 1.0);
+        }
+        let inf = f32::INFINITY;
+        let (min, max) = spheres.iter().fold(
+            (Vec3f(inf, inf, inf), Vec3f(-inf, -inf, -inf)),
+            |(min, max), (c, r)| {
+                (
+                    Vec3f(min.0.min(c.0 - r), min.1.min(c.1 - r), min.2.min(c.2 - r)),
+                    Vec3f(max.0.max(c.0 + r), max.1.max(c.1 + r), max.2.max(c.2 + r)),
+                )
+            },
+        );
+        let center = min.add(&max).mulf(0.5);
+        let radius = spheres
+            .iter()
+            .map(|(c, r)| c.sub(&center).length() + r)
+            .fold(0.0, f32::max);
+        (center, radius)
+    }
+
+    pub fn draw<'a>(&'a self, shader: &mut BasicShader<'a>) {
+        for (model, instance, model_m) in self.visible() {
+            let material = instance.material.as_ref().unwrap_or(&model.material);
+            shader.set_instance(model, material, &model_m);
+            if let Some(skin) = model.skin.as_ref() {
+                shader.joint_matrices = skin.pose(instance.clip, instance.time);
+                shader.joint_normals = shader.joint_matrices.iter().map(normal_matrix).collect();
+            }
+            if let Some(morph) = model.morph.as_ref() {
+                shader.morph_weights =
+                    morph.weights(instance.morph_clip, instance.time, &instance.weights);
+            }
+            for (faces, group) in model.model.runs() {
+                if group.is_some_and(|g| instance.hidden.iter().any(|h| h == g.part())) {
+                    continue;
+                }
+                // an instance's own material wins over the groups'
+                let group_material = group
+                    .and_then(|g| g.material.as_ref())
+                    .and_then(|m| model.materials.get(m));
+                shader.material = match group_material {
+                    Some(m) if instance.material.is_none() => m,
+                    _ => material,
+                };
+                for f in faces {
+                    let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
+                    for (v, vertex) in vertices.iter_mut().enumerate() {
+                        *vertex = shader.vertex(f, v);
+                    }
+                    triangle(&vertices[0], &vertices[1], &vertices[2], shader);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::Camera,
+        gbuffer::GBuffer,
+        hdr::HdrImage,
+        light::Light,
+        model::Wavefront,
+        shader::{ShaderConf, VertexCache},
+        tga::{Color, Image},
+    };
+
+    #[test]
+    fn instance_tint_and_uv_offset() {
+        // a square with every corner on the left texel of a white and blue texture
+        let wavefront = Wavefront::parse_string(
+            "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nvt 0.1 0.5\nvn 0 0 1\n\
+             f 1/1/1 2/1/1 3/1/1\nf 1/1/1 3/1/1 4/1/1\n"
+                .to_string(),
+        );
+        let mut texture = Image::new(2, 1);
+        texture.set_pixel(0, 0, Color(255, 255, 255));
+        texture.set_pixel(1, 0, Color(255, 0, 0));
+        let model = Model::new(wavefront, Image::new(0, 0), texture);
+
+        let at = |x: f32| {
+            Transform {
+                translation: Vec3f(x, 0.0, 0.0),
+                scale: Vec3f(0.3, 0.3, 0.3),
+                ..Transform::new()
+            }
+            .matrix()
+        };
+        let buffer = [
+            InstanceData {
+                tint: Vec3f(1.0, 0.5, 0.25),
+                ..InstanceData::new(&at(-0.5))
+            },
+            InstanceData {
+                uv_offset: (0.5, 0.0),
+                ..InstanceData::new(&at(0.5))
+            },
+        ];
+
+        let (width, height) = (100, 100);
+        let mut out_texture = HdrImage::new(width, height);
+        let mut z_buffer = Image::new(width, height);
+        let mut light_texture = Image::new(width, height);
+        let mut gbuffer = GBuffer::new(width, height);
+        let ramp = Image::new(0, 0);
+        let camera = Camera::new();
+        let lookat_m = camera.look_at_m();
+        let mut shader = BasicShader {
+            conf: ShaderConf::new(),
+            // flat white light leaves the albedo as it is
+            lights: vec![Light::Ambient {
+                color: Vec3f(1.0, 1.0, 1.0),
+                intensity: 1.0,
+            }],
+            env: None,
+            camera,
+            lookat_m,
+            lookat_mi: lookat_m.inverse().transpose(),
+            instance: InstanceData::new(&Transform::new().matrix()),
+            instances: &[],
+            model: &model,
+            material: &model.material,
+            ramp: &ramp,
+            out_texture: &mut out_texture,
+            z_buffer: &mut z_buffer,
+            light_texture: &mut light_texture,
+            gbuffer: &mut gbuffer,
+            cache: VertexCache::new(),
+            joint_matrices: Vec::new(),
+            joint_normals: Vec::new(),
+            morph_weights: Vec::new(),
+            varying_uv: Matrix::zeroed(),
+            varying_xy: Matrix::zeroed(),
+            varying_view: Matrix::zeroed(),
+            varying_skin: [None; 3],
+            varying_normal: Matrix::zeroed(),
+            varying_normal_delta: Matrix::zeroed(),
+            varying_color: Matrix::zeroed(),
+            vertices: [Vec3f::zeroed(); 3],
+            normal_face_vec: None,
+            face: 0,
+        };
+        draw_instanced(&model, &buffer, &mut shader);
+
+        // the left instance is tinted white, the right one samples the blue texel
+        let (mut left, mut right) = (0, 0);
+        for y in 0..height {
+            for x in 0..width {
+                if gbuffer.is_empty(x, y) {
+                    continue;
+                }
+                let Vec3f(r, g, b) = out_texture.pixel_at(x, y);
+                let expected = if x < width / 2 {
+                    left += 1;
+                    (1.0, 0.5, 0.25)
+                } else {
+                    right += 1;
+                    (0.0, 0.0, 1.0)
+                };
+                let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+                assert!(
+                    close(r, expected.0) && close(g, expected.1) && close(b, expected.2),
+                    "{:?} at {} {}",
+                    (r, g, b),
+                    x,
+                    y
+                );
+            }
+        }
+        assert!(left > 0 && right > 0);
+    }
+}
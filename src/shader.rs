@@ -1,9 +1,22 @@
+use std::f32::consts::PI;
 use std::mem;
 
 use crate::{
-    la::{barycentric, look_at, persp, to_screen_space, Matrix, MatrixI, Vec3f},
+    camera::Camera,
+    debug::{self, DebugView},
+    dof::Lens,
+    env::EnvView,
+    fog::Fog,
+    gbuffer::GBuffer,
+    hdr::{HdrImage, ToneMap},
+    la::{barycentric, eye, look_at, persp, to_screen_space, Matrix, MatrixI, Vec3f},
+    light::Light,
+    material::Material,
     model::Model,
+    scene::InstanceData,
+    skin,
     tga::{self, Color},
+    toon,
 };
 
 #[derive(Debug, Clone)]
@@ -13,6 +26,21 @@ pub struct ShaderConf {
     pub texture: bool,
     pub normals: bool,
     pub occlusion: bool,
+    pub pbr: bool,
+    pub ibl: bool,
+    pub exposure: f32,
+    pub tone_map: ToneMap,
+    pub ssao_radius: f32,
+    pub ssao_intensity: f32,
+    pub ssao_samples: usize,
+    pub fog: Fog,
+    pub cel: bool,
+    pub cel_bands: usize,
+    pub outlines: bool,
+    pub lens: Lens,
+    pub debug: DebugView,
+    pub normal_glyphs: bool,
+    pub raster: RasterState,
 }
 
 impl ShaderConf {
@@ -23,6 +51,120 @@ impl ShaderConf {
             texture: true,
             normals: true,
             occlusion: false,
+            pbr: false,
+            ibl: false,
+            exposure: 0.0,
+            tone_map: ToneMap::Aces,
+            ssao_radius: 0.1,
+            ssao_intensity: 1.0,
+            ssao_samples: 16,
+            fog: Fog::new(),
+            cel: false,
+            cel_bands: 3,
+            outlines: false,
+            lens: Lens::new(),
+            debug: DebugView::Off,
+            normal_glyphs: false,
+            raster: RasterState::new(),
+        }
+    }
+
+    // the settings are for the head in the default view, scaled to the camera's
+    pub fn framed(&self, camera: &Camera) -> Self {
+        ShaderConf {
+            ssao_radius: self.ssao_radius * camera.scale(),
+            fog: self.fog.framed(camera),
+            lens: Lens {
+                focus_distance: self.lens.focus_distance * camera.scale(),
+                ..self.lens
+            },
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CullMode {
+    None,
+    Back,
+    Front,
+}
+
+impl CullMode {
+    #[cfg(not(feature = "local"))]
+    pub fn next(&self) -> Self {
+        match self {
+            CullMode::Back => CullMode::Front,
+            CullMode::Front => CullMode::None,
+            CullMode::None => CullMode::Back,
+        }
+    }
+
+    #[cfg(not(feature = "local"))]
+    pub fn name(&self) -> &'static str {
+        match self {
+            CullMode::None => "None",
+            CullMode::Back => "Back",
+            CullMode::Front => "Front",
+        }
+    }
+
+    // none, back or front
+    pub fn parse(s: &str) -> CullMode {
+        match s.to_lowercase().as_str() {
+            "none" => CullMode::None,
+            "back" => CullMode::Back,
+            "front" => CullMode::Front,
+            _ => panic!("bad cull mode {}", s),
+        }
+    }
+}
+
+// order of the vertices of a front face as seen on the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Winding {
+    Ccw,
+    Cw,
+}
+
+impl Winding {
+    // ccw or cw
+    pub fn parse(s: &str) -> Winding {
+        match s.to_lowercase().as_str() {
+            "ccw" => Winding::Ccw,
+            "cw" => Winding::Cw,
+            _ => panic!("bad winding {}", s),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RasterState {
+    pub cull: CullMode,
+    pub front_face: Winding,
+    // back faces get their normal flipped instead of being lit from behind
+    pub two_sided: bool,
+}
+
+impl RasterState {
+    pub fn new() -> Self {
+        RasterState {
+            cull: CullMode::Back,
+            front_face: Winding::Ccw,
+            two_sided: false,
+        }
+    }
+
+    // area is the z of the screen space cross product of the triangle edges
+    pub fn is_front(&self, area: f32) -> bool {
+        (area >= 0.0) == (self.front_face == Winding::Ccw)
+    }
+
+    pub fn is_culled(&self, area: f32) -> bool {
+        match self.cull {
+            CullMode::None => false,
+            CullMode::Back => !self.is_front(area),
+            CullMode::Front => self.is_front(area),
         }
     }
 }
@@ -30,15 +172,23 @@ impl ShaderConf {
 pub trait Shader {
     fn vertex(&mut self, face: usize, vertex: usize) -> Vec3f;
     fn fragment(&mut self, bar: &Vec3f);
+
+    // instanced draws also pass the index into the instance buffer
+    fn vertex_instanced(&mut self, _instance: usize, face: usize, vertex: usize) -> Vec3f {
+        self.vertex(face, vertex)
+    }
+
+    fn raster(&self) -> RasterState {
+        RasterState::new()
+    }
 }
 
 pub struct LightShader<'a> {
     pub conf: ShaderConf,
     pub model: &'a Model,
-    pub out_texture: &'a mut tga::Image,
-    pub light_texture: &'a mut tga::Image,
+    pub out_texture: &'a mut HdrImage,
     pub occl_texture: &'a mut tga::Image,
-    pub z_buffer: &'a mut tga::Image,
+    pub ambient: &'a [f32],
     pub varying_uv: Matrix<3, 2>,
     pub varying_xy: Matrix<3, 3>,
 }
@@ -70,70 +220,306 @@ impl Shader for LightShader<'_> {
         let y = y.round() as i32;
         let z = z.round();
 
-        let current_z = self.z_buffer.pixel_at(x, y).0 as f32 / 255.0;
-        // let [[u],[v]] = self.varying_uv.mul(&bar_mtrx);
-        let mut total = 0.0;
-        // hacky screen space ambient occlusion
-        for yy in (y - 5).max(0)..(y + 5).min(self.out_texture.height) {
-            for xx in (x - 5).max(0)..(x + 5).min(self.out_texture.width) {
-                let surr_z = self.z_buffer.pixel_at(xx, yy).0 as f32 / 255.0;
-                if current_z <= 0.01 {
-                    continue;
-                }
-                if surr_z > current_z && surr_z - current_z > 0.01 {
-                    total += (surr_z - current_z).min(0.05);
-                }
-            }
-        }
-
-        total /= 2.0;
+        let ao = *self
+            .ambient
+            .get((x + y * self.out_texture.width) as usize)
+            .unwrap_or(&1.0);
 
         // check if not already set
         if self.occl_texture.pixel_at(x, y).0 == 0 {
-            self.occl_texture.set_pixel(
-                x,
-                y,
-                Color(
-                    (total * 254.0).min(254.0) as u8 + 1,
-                    (total * 254.0).min(254.0) as u8 + 1,
-                    (total * 254.0).min(254.0) as u8 + 1,
-                ),
-            );
-            let texture = self.out_texture.pixel_at(x, y);
-            let mut light = (2.0 * self.light_texture.pixel_at(x, y).0 as f32 / 255.0) * 2.0 - 2.0;
-            light -= total;
-            self.out_texture.set_pixel(x, y, texture.highlight(light));
+            let c = (ao * 254.0).clamp(0.0, 254.0) as u8 + 1;
+            self.occl_texture.set_pixel(x, y, Color(c, c, c));
+            let color = self.out_texture.pixel_at(x, y);
+            self.out_texture.set_pixel(x, y, color.mulf(ao));
+        }
+    }
+}
+
+// vertex stage output of one mesh vertex
+#[derive(Debug, Clone, Copy)]
+pub struct Transformed {
+    pub screen: Vec3f,
+    pub view: Vec3f,
+    pub uv: [f32; 2],
+    // blended joint normal matrices of skinned meshes
+    pub skin: Option<Matrix<4, 4>>,
+    // model space vertex normal, zero when the mesh has none
+    pub normal: Vec3f,
+    // model space normal offset of morphed meshes
+    pub normal_delta: Vec3f,
+    pub color: Vec3f,
+}
+
+// post transform cache, a slot per mesh vertex so shared vertices are
+// transformed once per draw
+pub struct VertexCache {
+    slots: Vec<Option<Transformed>>,
+    instance: Option<usize>,
+}
+
+impl VertexCache {
+    pub fn new() -> Self {
+        VertexCache {
+            slots: Vec::new(),
+            instance: None,
+        }
+    }
+
+    // empty for a draw of a mesh with len vertices
+    pub fn clear(&mut self, len: usize, instance: Option<usize>) {
+        self.slots.clear();
+        self.slots.resize(len, None);
+        self.instance = instance;
+    }
+
+    // instance of an instanced draw the cached vertices belong to
+    pub fn instance(&self) -> Option<usize> {
+        self.instance
+    }
+
+    pub fn get(&self, index: usize) -> Option<Transformed> {
+        self.slots.get(index).copied().flatten()
+    }
+
+    pub fn insert(&mut self, index: usize, transformed: Transformed) {
+        if let Some(slot) = self.slots.get_mut(index) {
+            *slot = Some(transformed);
         }
     }
 }
 
 pub struct BasicShader<'a> {
     pub conf: ShaderConf,
-    pub light_dir: Vec3f,
+    pub lights: Vec<Light>,
+    pub env: Option<EnvView<'a>>,
+    // depth range of the z buffer, lookat_m is its look at matrix
+    pub camera: Camera,
     pub lookat_m: Matrix<4, 4>,
     pub lookat_mi: Matrix<4, 4>,
+    // transform and attributes of the instance being drawn
+    pub instance: InstanceData,
+    pub instances: &'a [InstanceData],
     pub model: &'a Model,
-    pub out_texture: &'a mut tga::Image,
+    pub material: &'a Material,
+    // light ramp for cel shading
+    pub ramp: &'a tga::Image,
+    pub out_texture: &'a mut HdrImage,
     pub light_texture: &'a mut tga::Image,
     pub z_buffer: &'a mut tga::Image,
+    pub gbuffer: &'a mut GBuffer,
+    pub cache: VertexCache,
+    // posed joints of the skinned model being drawn, empty draws the bind pose
+    pub joint_matrices: Vec<Matrix<4, 4>>,
+    // normal matrices of the posed joints
+    pub joint_normals: Vec<Matrix<4, 4>>,
+    // morph target weights of the model being drawn, empty draws the base shape
+    pub morph_weights: Vec<f32>,
 
     pub varying_uv: Matrix<3, 2>,
     pub varying_xy: Matrix<3, 3>,
+    pub varying_view: Matrix<3, 3>,
+    pub varying_skin: [Option<Matrix<4, 4>>; 3],
+    pub varying_normal: Matrix<3, 3>,
+    pub varying_normal_delta: Matrix<3, 3>,
+    pub varying_color: Matrix<3, 3>,
     pub vertices: [Vec3f; 3],
     pub normal_face_vec: Option<Vec3f>,
+    pub face: usize,
+}
+
+impl<'a> BasicShader<'a> {
+    pub fn set_instance(
+        &mut self,
+        model: &'a Model,
+        material: &'a Material,
+        model_m: &Matrix<4, 4>,
+    ) {
+        self.model = model;
+        self.material = material;
+        self.instance = InstanceData::new(model_m);
+        self.joint_matrices.clear();
+        self.joint_normals.clear();
+        self.morph_weights.clear();
+        self.cache.clear(model.num_vertices(), None);
+    }
+
+    pub fn set_instances(
+        &mut self,
+        model: &'a Model,
+        material: &'a Material,
+        instances: &'a [InstanceData],
+    ) {
+        self.model = model;
+        self.material = material;
+        self.instances = instances;
+        self.joint_matrices.clear();
+        self.joint_normals.clear();
+        self.morph_weights.clear();
+        self.cache.clear(model.num_vertices(), None);
+    }
+
+    fn transform(&self, index: usize) -> Transformed {
+        let vertex = &self.model.mesh.vertices[index];
+        // morphs apply to the bind pose, before skinning
+        let (offset, normal_delta) = match self.model.morph.as_ref() {
+            Some(morph) if !self.morph_weights.is_empty() => {
+                morph.blend(index, &self.morph_weights)
+            }
+            _ => (Vec3f::zeroed(), Vec3f::zeroed()),
+        };
+        let position = vertex.position.add(&offset);
+        let skin = match self.model.skin.as_ref() {
+            Some(skin) if !self.joint_matrices.is_empty() => Some((
+                skin.blend(index, &self.joint_matrices),
+                skin.blend(index, &self.joint_normals),
+            )),
+            _ => None,
+        };
+        let position = match skin.as_ref() {
+            Some((m, _)) => look_at(m, &position),
+            None => position,
+        };
+        let skin = skin.map(|(_, normals)| normals);
+        let world = look_at(&self.instance.model_m, &position);
+        let view = look_at(&self.lookat_m, &world);
+        let persp = persp(5.0, &view);
+        let ss = to_screen_space(&persp, self.out_texture.width, self.out_texture.height);
+        Transformed {
+            screen: Vec3f(ss.0, ss.1, self.camera.depth(persp.2)),
+            view,
+            uv: vertex.uv,
+            skin,
+            normal: vertex.normal.add(&normal_delta),
+            normal_delta,
+            color: vertex.color,
+        }
+    }
+
+    // diffuse light and specular color in linear space, texture is applied by the caller
+    fn phong(&self, position: &Vec3f, normal: &Vec3f) -> (Vec3f, Vec3f) {
+        let (mut diffuse, mut specular) = match &self.env {
+            Some(env) => {
+                // schlick fresnel for a dielectric, lobe about as wide as the 23 power below
+                let n_v = normal.2.max(0.0); // cam on z
+                let fresnel = 0.04 + 0.96 * (1.0 - n_v).powi(5);
+                let reflected = normal.mulf(2.0 * n_v).sub(&Vec3f(0.0, 0.0, 1.0));
+                (
+                    env.irradiance(normal).mulf(1.0 / PI),
+                    env.specular(&reflected, 0.3).mulf(fresnel),
+                )
+            }
+            None => (Vec3f::zeroed(), Vec3f::zeroed()),
+        };
+        for light in self.lights.iter() {
+            // environment replaces flat ambient light
+            if self.env.is_none() {
+                diffuse = diffuse.add(&light.ambient());
+            }
+            if let Some((light_dir, color)) = light.incident(position) {
+                let light = normal.mul(&light_dir);
+                if light <= 0.0 {
+                    continue;
+                }
+                let reflected = normal.mulf(light * 2.0).sub(&light_dir).normalize();
+                let light_spec = reflected.2.max(0.0).powf(23.0); // cam on z
+                if self.conf.cel {
+                    // flat bands and a hard edged highlight
+                    let light_spec = if light_spec > 0.5 { 1.0 } else { 0.0 };
+                    diffuse = diffuse.add(&color.mulv(&toon::lookup(self.ramp, light)));
+                    specular = specular.add(&color.mulf(light_spec * 0.9));
+                    continue;
+                }
+                diffuse = diffuse.add(&color.mulf(light));
+                specular = specular.add(&color.mulf(light_spec * 0.9));
+            }
+        }
+
+        // unlit texture when diffuse light is off
+        let diffuse = if self.conf.diff_light {
+            diffuse
+        } else {
+            Vec3f(1.0, 1.0, 1.0)
+        };
+        let specular = if self.conf.spec_light {
+            specular
+        } else {
+            Vec3f::zeroed()
+        };
+        (diffuse, specular)
+    }
+
+    // per face tangent along u, taken from the uv layout of the triangle
+    fn tangent(&self, normal: &Vec3f) -> Vec3f {
+        let (vv, uv) = (&self.varying_view, &self.varying_uv);
+        let p = |i: usize| Vec3f(vv[0][i], vv[1][i], vv[2][i]);
+        let (e1, e2) = (p(1).sub(&p(0)), p(2).sub(&p(0)));
+        let (du1, dv1) = (uv[0][1] - uv[0][0], uv[1][1] - uv[1][0]);
+        let (du2, dv2) = (uv[0][2] - uv[0][0], uv[1][2] - uv[1][0]);
+        let t = e1
+            .mulf(dv2)
+            .sub(&e2.mulf(dv1))
+            .mulf((du1 * dv2 - du2 * dv1).signum());
+        t.sub(&normal.mulf(normal.mul(&t))).normalize()
+    }
+
+    fn debug_color(&self, u: f32, v: f32, normal: &Vec3f) -> Vec3f {
+        match self.conf.debug {
+            DebugView::WorldNormals => {
+                let world: Vec3f = self
+                    .lookat_m
+                    .transpose()
+                    .mul(&normal.embed::<4>(0.0))
+                    .into();
+                debug::vector(&world)
+            }
+            DebugView::ViewNormals => debug::vector(normal),
+            DebugView::UvChecker => debug::uv_checker(u, v),
+            DebugView::UvGradient => debug::uv_gradient(u, v),
+            DebugView::Tangents => debug::vector(&self.tangent(normal)),
+            DebugView::TriangleIds => debug::triangle_id(self.face),
+            DebugView::Off | DebugView::Overdraw => Vec3f::zeroed(),
+        }
+    }
 }
 
 impl Shader for BasicShader<'_> {
     fn vertex(&mut self, face: usize, vertex: usize) -> Vec3f {
-        let v = self.model.vertex(face, vertex);
-        let t = self.model.texture_coords(face, vertex);
+        self.face = face;
+        let index = self.model.index(face, vertex);
+        let Transformed {
+            screen: ss,
+            view,
+            uv,
+            skin,
+            normal,
+            normal_delta,
+            color,
+        } = match self.cache.get(index) {
+            Some(transformed) => transformed,
+            None => {
+                let transformed = self.transform(index);
+                self.cache.insert(index, transformed);
+                transformed
+            }
+        };
 
         for i in 0..2 {
-            self.varying_uv[i][vertex] = t[i];
+            self.varying_uv[i][vertex] = uv[i];
         }
 
-        let persp = persp(5.0, &look_at(&self.lookat_m, &v));
-        let ss = to_screen_space(&persp, self.out_texture.width, self.out_texture.height);
+        self.varying_view[0][vertex] = view.0;
+        self.varying_view[1][vertex] = view.1;
+        self.varying_view[2][vertex] = view.2;
+        self.varying_skin[vertex] = skin;
+        self.varying_normal[0][vertex] = normal.0;
+        self.varying_normal[1][vertex] = normal.1;
+        self.varying_normal[2][vertex] = normal.2;
+        self.varying_normal_delta[0][vertex] = normal_delta.0;
+        self.varying_normal_delta[1][vertex] = normal_delta.1;
+        self.varying_normal_delta[2][vertex] = normal_delta.2;
+        self.varying_color[0][vertex] = color.0;
+        self.varying_color[1][vertex] = color.1;
+        self.varying_color[2][vertex] = color.2;
 
         self.vertices[vertex] = ss;
 
@@ -165,54 +551,142 @@ impl Shader for BasicShader<'_> {
         let x = x.round() as i32;
         let y = y.round() as i32;
         let z = z.round() as u8;
-        if z <= self.z_buffer.pixel_at(x, y).0
-            || x < 0
-            || x >= self.out_texture.width
-            || y < 0
-            || y >= self.out_texture.height
-        {
+        if x < 0 || x >= self.out_texture.width || y < 0 || y >= self.out_texture.height {
+            return;
+        }
+        self.gbuffer.count(x, y);
+        // depth tested against the view space z of the gbuffer, the 8 bit buffer
+        // is only written for looking at. it still clips past the far plane
+        let position: Vec3f = self.varying_view.mul(&bar_mtrx).into();
+        if z == 0 || position.2 <= self.gbuffer.depth_at(x, y) {
             return;
         }
 
         let [[u], [v]] = self.varying_uv.mul(&bar_mtrx);
+        let (u, v) = self.instance.uv(u, v);
 
-        let txt = if self.conf.texture {
-            self.model.texture(u, v)
+        // vertex colors stand in for a missing texture
+        let albedo = if !self.model.has_texture() && self.model.has_colors() {
+            self.varying_color.mul(&bar_mtrx).into()
+        } else if self.conf.texture {
+            self.model.texture(u, v).to_linear()
         } else {
-            Color(150, 150, 150)
+            Color(150, 150, 150).to_linear()
         };
+        let albedo = albedo.mulv(&self.instance.tint);
+        let model_space = self.model.has_normal_map() && !self.model.tangent_normals;
         let normal_vec = if self.conf.normals {
-            self.lookat_mi
-                .mul(&(self.model.normal(u, v)).embed::<4>(0.0))
-                .into()
+            let n = if model_space {
+                let n = self.model.normal(u, v);
+                // the normal map is in model space, so the morphed change of the
+                // vertex normals can be added on top
+                if self.morph_weights.is_empty() {
+                    n
+                } else {
+                    let delta: Vec3f = self.varying_normal_delta.mul(&bar_mtrx).into();
+                    n.add(&delta).normalize()
+                }
+            } else {
+                self.varying_normal.mul(&bar_mtrx).into()
+            };
+            let n = n.embed::<4>(0.0);
+            // joint normal matrices blended across the triangle
+            let n = match &self.varying_skin {
+                [Some(a), Some(b), Some(c)] => {
+                    skin::weighted_sum([(a, bar.0), (b, bar.1), (c, bar.2)]).mul(&n)
+                }
+                _ => n,
+            };
+            let n: Vec3f = self.lookat_mi.mul(&self.instance.model_mi.mul(&n)).into();
+            if self.model.has_normal_map() && self.model.tangent_normals {
+                let n = n.normalize();
+                let t = self.tangent(&n);
+                let m = self.model.normal(u, v);
+                t.mulf(m.0).add(&n.cross(&t).mulf(m.1)).add(&n.mulf(m.2))
+            } else {
+                n
+            }
         } else {
             *self.normal_face_vec.as_ref().unwrap()
         };
+        // meshes without normals shade flat
+        let normal_vec = if normal_vec.length() < 1e-6 {
+            *self.normal_face_vec.as_ref().unwrap()
+        } else {
+            normal_vec
+        };
         let normal_vec = normal_vec.normalize();
+        let front = self
+            .conf
+            .raster
+            .is_front(self.normal_face_vec.as_ref().unwrap().2);
+        let normal_vec = if self.conf.raster.two_sided && !front {
+            normal_vec.mulf(-1.0)
+        } else {
+            normal_vec
+        };
 
-        let light = normal_vec.mul(&self.light_dir);
-        let reflected = normal_vec
-            .mulf(normal_vec.mul(&self.light_dir) * 2.0)
-            .sub(&self.light_dir)
-            .normalize();
-        let light_spec = reflected.2.powf(23.0); // cam on z
+        let (color, light) = if self.conf.debug != DebugView::Off {
+            let c = self.debug_color(u, v, &normal_vec);
+            (c, c)
+        } else if self.conf.pbr && !self.conf.cel {
+            // cel shading is built on top of the phong lobes
+            let surface = self.material.surface(u, v, albedo);
+            let lit = surface.shade(
+                &self.conf,
+                &self.lights,
+                self.env.as_ref(),
+                &position,
+                &normal_vec,
+                &Vec3f(0.0, 0.0, 1.0), // cam on z
+            );
+            (lit, lit)
+        } else {
+            let (diffuse, specular) = self.phong(&position, &normal_vec);
+            (albedo.mulv(&diffuse).add(&specular), diffuse.add(&specular))
+        };
+        let color = if self.conf.fog.per_fragment
+            && self.conf.fog.enabled()
+            && self.conf.debug == DebugView::Off
+        {
+            self.conf
+                .fog
+                .apply(&color, &position, &self.lookat_mi.transpose())
+        } else {
+            color
+        };
 
-        let mut highlight = if self.conf.diff_light { light } else { 0.0f32 };
-        highlight += if self.conf.spec_light { light_spec * 0.9 } else { 0.0 };
+        self.light_texture
+            .set_pixel(x, y, Color::from_linear(&light));
+        self.out_texture.set_pixel(x, y, color);
+        self.z_buffer.set_pixel(x, y, tga::Color(z, z, z));
+
+        // geometric normal, normal maps only add noise to the screen space passes
+        let vv = &self.varying_view;
+        let a = Vec3f(vv[0][0], vv[1][0], vv[2][0]);
+        let b = Vec3f(vv[0][1], vv[1][1], vv[2][1]);
+        let c = Vec3f(vv[0][2], vv[1][2], vv[2][2]);
+        let face_normal = b.sub(&a).cross(&c.sub(&a)).normalize();
+        // whatever the winding, the screen space passes want it facing the camera
+        let face_normal = if face_normal.mul(&eye(5.0).sub(&a)) < 0.0 {
+            face_normal.mulf(-1.0)
+        } else {
+            face_normal
+        };
+        self.gbuffer
+            .set(x, y, vv.mul(&bar_mtrx).into(), face_normal);
+    }
 
-        let hc = (((highlight + 2.0) / 2.0) * 255.0 / 2.0).round() as u8;
-        self.light_texture.set_pixel(x, y, Color(hc, hc, hc));
+    fn vertex_instanced(&mut self, instance: usize, face: usize, vertex: usize) -> Vec3f {
+        if self.cache.instance() != Some(instance) {
+            self.instance = self.instances[instance];
+            self.cache.clear(self.model.num_vertices(), Some(instance));
+        }
+        self.vertex(face, vertex)
+    }
 
-        self.out_texture.set_pixel(
-            x,
-            y,
-            if self.conf.occlusion {
-                txt
-            } else {
-                txt.highlight(highlight)
-            },
-        );
-        self.z_buffer.set_pixel(x, y, tga::Color(z, z, z))
+    fn raster(&self) -> RasterState {
+        self.conf.raster
     }
 }
 
@@ -221,7 +695,7 @@ pub fn triangle(v1: &Vec3f, v2: &Vec3f, v3: &Vec3f, sh: &mut dyn Shader) {
         .sub(&Vec3f(v1.0, v1.1, v1.2))
         .cross(&Vec3f(v3.0, v3.1, v3.2).sub(&Vec3f(v1.0, v1.1, v1.2)));
 
-    if z.2 < 0.0 {
+    if sh.raster().is_culled(z.2) {
         return;
     }
 
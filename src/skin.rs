@@ -0,0 +1,256 @@
+use crate::{
+    la::{interpolatev, smoothstep, Matrix, MatrixI, Quat, Vec3f},
+    model::Mesh,
+};
+
+// joint transform relative to its parent
+#[derive(Debug, Clone, Copy)]
+pub struct JointPose {
+    pub translation: Vec3f,
+    pub rotation: Quat,
+    pub scale: Vec3f,
+}
+
+impl JointPose {
+    pub fn new() -> Self {
+        JointPose {
+            translation: Vec3f::zeroed(),
+            rotation: Quat::identity(),
+            scale: Vec3f(1.0, 1.0, 1.0),
+        }
+    }
+
+    // scale, rotate, then translate
+    pub fn matrix(&self) -> Matrix<4, 4> {
+        let mut m = self.rotation.matrix();
+        let Vec3f(x, y, z) = self.scale;
+        for row in m.iter_mut().take(3) {
+            row[0] *= x;
+            row[1] *= y;
+            row[2] *= z;
+        }
+        let Vec3f(x, y, z) = self.translation;
+        m[0][3] = x;
+        m[1][3] = y;
+        m[2][3] = z;
+        m
+    }
+}
+
+pub struct Joint {
+    // parents come before their children
+    pub parent: Option<usize>,
+    // model space to joint space in the bind pose
+    pub inverse_bind: Matrix<4, 4>,
+    pub rest: JointPose,
+}
+
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+    // model space of whatever the root joints hang from
+    pub root: Matrix<4, 4>,
+}
+
+impl Skeleton {
+    pub fn rest_pose(&self) -> Vec<JointPose> {
+        self.joints.iter().map(|j| j.rest).collect()
+    }
+
+    // joint to model space for every joint of the pose
+    pub fn world_matrices(&self, pose: &[JointPose]) -> Vec<Matrix<4, 4>> {
+        let mut world: Vec<Matrix<4, 4>> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(pose.iter()) {
+            let m = local.matrix();
+            let m = match joint.parent {
+                Some(p) => world[p].mul(&m),
+                None => self.root.mul(&m),
+            };
+            world.push(m);
+        }
+        world
+    }
+
+    // bind pose model space to posed model space, what vertices are blended with
+    pub fn skinning_matrices(&self, pose: &[JointPose]) -> Vec<Matrix<4, 4>> {
+        self.world_matrices(pose)
+            .iter()
+            .zip(self.joints.iter())
+            .map(|(world, joint)| world.mul(&joint.inverse_bind))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Keys {
+    Translation(Vec<Vec3f>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3f>),
+}
+
+// keyframes of one joint property, a value per time
+pub struct Channel {
+    pub joint: usize,
+    pub times: Vec<f32>,
+    pub keys: Keys,
+}
+
+// keyframes around time and how far between them, held at both ends
+pub fn span(times: &[f32], time: f32) -> (usize, usize, f32) {
+    let last = times.len() - 1;
+    match times.iter().position(|t| *t > time) {
+        Some(0) => (0, 0, 0.0),
+        Some(next) => {
+            let (a, b) = (times[next - 1], times[next]);
+            (next - 1, next, (time - a) / (b - a))
+        }
+        None => (last, last, 0.0),
+    }
+}
+
+impl Channel {
+    fn apply(&self, pose: &mut JointPose, time: f32) {
+        if self.times.is_empty() {
+            return;
+        }
+        let (a, b, t) = span(&self.times, time);
+        match &self.keys {
+            Keys::Translation(v) => pose.translation = interpolatev(&v[a], &v[b], t),
+            Keys::Rotation(q) => pose.rotation = q[a].slerp(&q[b], t),
+            Keys::Scale(v) => pose.scale = interpolatev(&v[a], &v[b], t),
+        }
+    }
+}
+
+pub struct Clip {
+    pub name: String,
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+impl Clip {
+    // looping, joints without channels keep their rest pose
+    pub fn sample(&self, skeleton: &Skeleton, time: f32) -> Vec<JointPose> {
+        let time = if self.duration > 0.0 {
+            time.rem_euclid(self.duration)
+        } else {
+            0.0
+        };
+        let mut pose = skeleton.rest_pose();
+        for channel in self.channels.iter() {
+            if let Some(joint) = pose.get_mut(channel.joint) {
+                channel.apply(joint, time);
+            }
+        }
+        pose
+    }
+}
+
+pub fn weighted_sum<'m>(terms: impl IntoIterator<Item = (&'m Matrix<4, 4>, f32)>) -> Matrix<4, 4> {
+    let mut res: Matrix<4, 4> = Matrix::zeroed();
+    for (m, w) in terms {
+        for y in 0..4 {
+            for x in 0..4 {
+                res[y][x] += w * m[y][x];
+            }
+        }
+    }
+    res
+}
+
+pub struct Skin {
+    pub skeleton: Skeleton,
+    // up to four joints per mesh vertex, weights add up to one
+    pub joints: Vec<[usize; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    pub clips: Vec<Clip>,
+}
+
+impl Skin {
+    // skinning matrices of a clip at time, the rest pose without one
+    pub fn pose(&self, clip: Option<usize>, time: f32) -> Vec<Matrix<4, 4>> {
+        let pose = match clip.and_then(|c| self.clips.get(c)) {
+            Some(clip) => clip.sample(&self.skeleton, time),
+            None => self.skeleton.rest_pose(),
+        };
+        self.skeleton.skinning_matrices(&pose)
+    }
+
+    // linear blend of the joint matrices of a mesh vertex
+    pub fn blend(&self, index: usize, matrices: &[Matrix<4, 4>]) -> Matrix<4, 4> {
+        weighted_sum(
+            self.joints[index]
+                .iter()
+                .zip(self.weights[index].iter())
+                .filter(|(_, w)| **w > 0.0)
+                .map(|(j, w)| (&matrices[*j], *w)),
+        )
+    }
+
+    // neck and head joints with nodding and turning clips, weights fade across
+    // the neck. lets heads without a skeleton of their own move
+    pub fn nod(mesh: &Mesh) -> Self {
+        let neck = Vec3f(0.0, -0.6, -0.1);
+        let head = Vec3f(0.0, 0.25, 0.0);
+        let at = |p: Vec3f| JointPose {
+            translation: p,
+            ..JointPose::new()
+        };
+        let joints = vec![
+            Joint {
+                parent: None,
+                inverse_bind: at(neck.mulf(-1.0)).matrix(),
+                rest: at(neck),
+            },
+            Joint {
+                parent: Some(0),
+                inverse_bind: at(neck.add(&head).mulf(-1.0)).matrix(),
+                rest: at(head),
+            },
+        ];
+        let weights = mesh
+            .vertices
+            .iter()
+            .map(|v| {
+                let w = smoothstep(-0.55, -0.25, v.position.1);
+                [1.0 - w, w, 0.0, 0.0]
+            })
+            .collect();
+        let x = |a: f32| Quat::from_axis_angle(&Vec3f(1.0, 0.0, 0.0), a);
+        let y = |a: f32| Quat::from_axis_angle(&Vec3f(0.0, 1.0, 0.0), a);
+        Skin {
+            skeleton: Skeleton {
+                joints,
+                root: JointPose::new().matrix(),
+            },
+            joints: vec![[0, 1, 0, 0]; mesh.vertices.len()],
+            weights,
+            clips: vec![
+                Clip {
+                    name: "Nod".to_string(),
+                    duration: 2.0,
+                    channels: vec![Channel {
+                        joint: 1,
+                        times: vec![0.0, 0.5, 1.0, 1.5, 2.0],
+                        keys: Keys::Rotation(vec![x(0.0), x(0.35), x(0.0), x(-0.15), x(0.0)]),
+                    }],
+                },
+                Clip {
+                    name: "Turn".to_string(),
+                    duration: 3.0,
+                    channels: vec![
+                        Channel {
+                            joint: 0,
+                            times: vec![0.0, 0.75, 2.25, 3.0],
+                            keys: Keys::Rotation(vec![y(0.0), y(0.2), y(-0.2), y(0.0)]),
+                        },
+                        Channel {
+                            joint: 1,
+                            times: vec![0.0, 0.75, 2.25, 3.0],
+                            keys: Keys::Rotation(vec![y(0.0), y(0.5), y(-0.5), y(0.0)]),
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+}
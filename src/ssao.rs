@@ -0,0 +1,136 @@
+use crate::{
+    gbuffer::GBuffer,
+    la::{interpolate, persp, smoothstep, to_screen_space, Rng, Vec3f},
+    shader::ShaderConf,
+};
+
+const NOISE_SIZE: i32 = 4;
+const BIAS: f32 = 0.005;
+const BLUR_RADIUS: i32 = 2;
+
+// screen space ambient occlusion
+// samples a normal oriented hemisphere around every visible point
+// and checks how many samples end up behind the depth buffer
+pub struct Ssao {
+    pub radius: f32,
+    pub intensity: f32,
+    kernel: Vec<Vec3f>,
+    noise: Vec<Vec3f>,
+}
+
+impl Ssao {
+    pub fn new(conf: &ShaderConf) -> Self {
+        let mut rng = Rng::new(0x5eed);
+        let samples = conf.ssao_samples.max(1);
+        let kernel = (0..samples)
+            .map(|i| {
+                let s = Vec3f(
+                    rng.next_f32() * 2.0 - 1.0,
+                    rng.next_f32() * 2.0 - 1.0,
+                    rng.next_f32(),
+                )
+                .normalize()
+                .mulf(rng.next_f32());
+                // more samples closer to the origin
+                let scale = i as f32 / samples as f32;
+                s.mulf(interpolate(0.1, 1.0, scale * scale))
+            })
+            .collect();
+        // random rotations around the normal, tiled over the screen
+        let noise = (0..NOISE_SIZE * NOISE_SIZE)
+            .map(|_| Vec3f(rng.next_f32() * 2.0 - 1.0, rng.next_f32() * 2.0 - 1.0, 0.0))
+            .collect();
+
+        Ssao {
+            radius: conf.ssao_radius,
+            intensity: conf.ssao_intensity,
+            kernel,
+            noise,
+        }
+    }
+
+    // returns blurred ambient term per pixel, 1.0 - not occluded at all
+    pub fn occlusion(&self, gbuffer: &GBuffer) -> Vec<f32> {
+        let mut ao = vec![1.0; (gbuffer.width * gbuffer.height) as usize];
+        for y in 0..gbuffer.height {
+            for x in 0..gbuffer.width {
+                if !gbuffer.is_empty(x, y) {
+                    ao[(x + y * gbuffer.width) as usize] = self.sample(gbuffer, x, y);
+                }
+            }
+        }
+        self.blur(gbuffer, &ao)
+    }
+
+    fn sample(&self, gbuffer: &GBuffer, x: i32, y: i32) -> f32 {
+        let position = gbuffer.position_at(x, y);
+        let normal = gbuffer.normal_at(x, y).normalize();
+        let rvec = self.noise[((x % NOISE_SIZE) + (y % NOISE_SIZE) * NOISE_SIZE) as usize];
+
+        // gram-schmidt, tangent space basis with random rotation
+        let tangent = rvec.sub(&normal.mulf(rvec.mul(&normal)));
+        let tangent = if tangent.length() < 1e-4 {
+            Vec3f(1.0, 0.0, 0.0).cross(&normal)
+        } else {
+            tangent
+        }
+        .normalize();
+        let bitangent = normal.cross(&tangent);
+
+        let mut occlusion = 0.0;
+        for k in self.kernel.iter() {
+            let sample = tangent
+                .mulf(k.0)
+                .add(&bitangent.mulf(k.1))
+                .add(&normal.mulf(k.2))
+                .mulf(self.radius)
+                .add(&position);
+
+            let ss = to_screen_space(&persp(5.0, &sample), gbuffer.width, gbuffer.height);
+            let (sx, sy) = (ss.0.round() as i32, ss.1.round() as i32);
+            if gbuffer.is_empty(sx, sy) {
+                continue;
+            }
+            let scene_z = gbuffer.depth_at(sx, sy);
+            // ignore geometry that is far away from the sampled point
+            let range = smoothstep(0.0, 1.0, self.radius / (position.2 - scene_z).abs());
+            if scene_z >= sample.2 + BIAS {
+                occlusion += range;
+            }
+        }
+
+        let ao = 1.0 - occlusion / self.kernel.len() as f32;
+        1.0 - (1.0 - ao) * self.intensity
+    }
+
+    // depth aware blur, hides the noise pattern without bleeding over edges
+    fn blur(&self, gbuffer: &GBuffer, ao: &[f32]) -> Vec<f32> {
+        let mut res = ao.to_vec();
+        let sigma_z = self.radius * 0.5;
+        for y in 0..gbuffer.height {
+            for x in 0..gbuffer.width {
+                if gbuffer.is_empty(x, y) {
+                    continue;
+                }
+                let z = gbuffer.depth_at(x, y);
+                let mut total = 0.0;
+                let mut weights = 0.0;
+                for yy in (y - BLUR_RADIUS).max(0)..=(y + BLUR_RADIUS).min(gbuffer.height - 1) {
+                    for xx in (x - BLUR_RADIUS).max(0)..=(x + BLUR_RADIUS).min(gbuffer.width - 1) {
+                        if gbuffer.is_empty(xx, yy) {
+                            continue;
+                        }
+                        let d = (((xx - x) * (xx - x) + (yy - y) * (yy - y)) as f32)
+                            / (2.0 * (BLUR_RADIUS * BLUR_RADIUS) as f32);
+                        let dz = (gbuffer.depth_at(xx, yy) - z) / sigma_z;
+                        let w = (-d - dz * dz).exp();
+                        total += ao[(xx + yy * gbuffer.width) as usize] * w;
+                        weights += w;
+                    }
+                }
+                res[(x + y * gbuffer.width) as usize] = total / weights;
+            }
+        }
+        res
+    }
+}
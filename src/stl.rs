@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs};
+
+use crate::{la::Vec3f, model::Wavefront};
+
+pub fn parse_file(file: String) -> Wavefront {
+    let bytes = fs::read(&file).unwrap_or_else(|e| panic!("can't read {}: {}", file, e));
+    parse(&bytes).unwrap_or_else(|e| panic!("{}: {}", file, e))
+}
+
+// binary when the triangle count in the header fits in the file, some exporters
+// pad the end, unless it reads as ascii text. binary headers often start with
+// "solid" too. corners at the same position are welded, facets keep their flat normals
+pub fn parse(bytes: &[u8]) -> Result<Wavefront, String> {
+    let count = bytes
+        .get(80..84)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize);
+    let fits = matches!(count.and_then(|c| c.checked_mul(50)), Some(n) if bytes.len() >= 84 + n);
+    let text = bytes.starts_with(b"solid")
+        && bytes
+            .iter()
+            .all(|b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+    let facets = if fits && !text {
+        binary(bytes, count.unwrap())
+    } else {
+        ascii(&String::from_utf8_lossy(bytes))?
+    };
+
+    let mut welded: HashMap<[u32; 3], i32> = HashMap::new();
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut faces = Vec::new();
+    let mut normal_indices = Vec::new();
+    for (normal, corners) in facets {
+        let face = corners.map(|p| {
+            *welded
+                .entry([p.0.to_bits(), p.1.to_bits(), p.2.to_bits()])
+                .or_insert_with(|| {
+                    vertices.push(p);
+                    vertices.len() as i32 - 1
+                })
+        });
+        // plenty of exporters leave the normal zeroed
+        let [a, b, c] = corners;
+        let normal = if normal.length() > 0.0 {
+            normal.normalize()
+        } else {
+            b.sub(&a).cross(&c.sub(&a)).normalize()
+        };
+        normals.push(normal);
+        let n = normals.len() as i32 - 1;
+        faces.push((face, [-1; 3]));
+        normal_indices.push([n, n, n]);
+    }
+    Ok(Wavefront::new(
+        vertices,
+        faces,
+        normals,
+        normal_indices,
+        Vec::new(),
+    ))
+}
+
+fn binary(bytes: &[u8], count: usize) -> Vec<(Vec3f, [Vec3f; 3])> {
+    bytes[84..84 + count * 50]
+        .chunks_exact(50)
+        .map(|c| {
+            let f = |p: usize| f32::from_le_bytes([c[p], c[p + 1], c[p + 2], c[p + 3]]);
+            let v = |p: usize| Vec3f(f(p), f(p + 4), f(p + 8));
+            (v(0), [v(12), v(24), v(36)])
+        })
+        .collect()
+}
+
+fn ascii(text: &str) -> Result<Vec<(Vec3f, [Vec3f; 3])>, String> {
+    let mut facets = Vec::new();
+    let mut normal = Vec3f::zeroed();
+    let mut corners = Vec::new();
+    for (i, l) in text.lines().enumerate() {
+        let mut items = l.split_ascii_whitespace();
+        let vec = |items: &mut std::str::SplitAsciiWhitespace| {
+            let mut next = || {
+                items
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .ok_or_else(|| format!("bad stl line {}: {}", i + 1, l.trim()))
+            };
+            Ok::<_, String>(Vec3f(next()?, next()?, next()?))
+        };
+        match items.next() {
+            Some("facet") => {
+                items.next(); // normal
+                normal = vec(&mut items)?;
+                corners.clear();
+            }
+            Some("vertex") => corners.push(vec(&mut items)?),
+            // polygons with more corners are fanned out
+            Some("endfacet") => {
+                for i in 1..corners.len().saturating_sub(1) {
+                    facets.push((normal, [corners[0], corners[i], corners[i + 1]]));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(facets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary_stl(header: &[u8], facets: &[[f32; 12]], padding: usize) -> Vec<u8> {
+        let mut v = header.to_vec();
+        v.resize(80, b' ');
+        v.extend_from_slice(&(facets.len() as u32).to_le_bytes());
+        for f in facets {
+            f.iter().for_each(|x| v.extend_from_slice(&x.to_le_bytes()));
+            v.extend_from_slice(&[0, 0]);
+        }
+        v.resize(v.len() + padding, 0);
+        v
+    }
+
+    // two triangles of a unit square sharing an edge
+    const SQUARE: [[f32; 12]; 2] = [
+        [0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0],
+    ];
+
+    #[test]
+    fn ascii_stl() {
+        let wf = parse(
+            b"solid square
+              facet normal 0 0 1
+                outer loop
+                  vertex 0 0 0
+                  vertex 1 0 0
+                  vertex 1 1 0
+                endloop
+              endfacet
+              facet normal 0 0 0
+                outer loop
+                  vertex 0 0 0
+                  vertex 1 1 0
+                  vertex 0 1 0
+                endloop
+              endfacet
+            endsolid square",
+        )
+        .unwrap();
+        assert_eq!((wf.vertices.len(), wf.faces.len()), (4, 2));
+        // the zeroed normal comes from the winding
+        let Vec3f(x, y, z) = wf.normals[1];
+        assert_eq!((x, y, z), (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn binary_stl_with_solid_header_and_padding() {
+        for padding in [0, 4] {
+            let wf = parse(&binary_stl(
+                b"solid exported by some tool",
+                &SQUARE,
+                padding,
+            ))
+            .unwrap();
+            assert_eq!(
+                (wf.vertices.len(), wf.faces.len()),
+                (4, 2),
+                "padding {}",
+                padding
+            );
+            assert_eq!(wf.faces[1].0, [0, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn empty_binary_stl() {
+        let wf = parse(&binary_stl(b"", &[], 0)).unwrap();
+        assert!(wf.faces.is_empty());
+    }
+
+    #[test]
+    fn bad_ascii_stl() {
+        let err = parse(b"solid x\nfacet normal 0 0 1\nvertex 0 zero 0\n").err();
+        assert_eq!(err.unwrap(), "bad stl line 3: vertex 0 zero 0");
+    }
+}
@@ -6,23 +6,44 @@ use std::io::Write;
 use std::mem;
 use std::slice;
 
+use crate::la::Vec3f;
+
 #[derive(Clone, Debug, Copy)]
 pub struct Color(pub u8, pub u8, pub u8);
 
 impl Color {
-    pub fn highlight(self, p: f32) -> Self {
-        let Color(r, g, b) = self;
-        // Color((r as f32 *p).min(255.0) as u8, (g as f32 *p).min(255.0) as u8, (b  as f32 * p).min(255.0) as u8,)
-        let fr = ((r as f32) / 255.0).powf(1.0 - p / 2.3);
-        let fg = ((g as f32) / 255.0).powf(1.0 - p / 2.3);
-        let fb = ((b as f32) / 255.0).powf(1.0 - p / 2.3);
-        Color((fr * 255.0) as u8, (fg * 255.0) as u8, (fb * 255.0) as u8)
+    // srgb encoded color to linear [0, 1] floats
+    // Color keeps tga's bgr order, linear colors are rgb
+    pub fn to_linear(self) -> Vec3f {
+        let decode = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Vec3f(decode(self.2), decode(self.1), decode(self.0))
+    }
+
+    pub fn from_linear(c: &Vec3f) -> Self {
+        let encode = |c: f32| {
+            let c = c.clamp(0.0, 1.0);
+            let c = if c <= 0.0031308 {
+                c * 12.92
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            (c * 255.0).round() as u8
+        };
+        Color(encode(c.2), encode(c.1), encode(c.0))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct ColorA(pub u8, pub u8, pub u8, pub u8);
 
+#[derive(Clone)]
 pub struct Image {
     pub width: i32,
     pub height: i32,
@@ -63,18 +84,6 @@ impl Image {
             .unwrap_or(&Color(0, 0, 0))
     }
 
-    pub fn apply_gamma(self: &mut Image, gamma: f32) {
-        for c in self.data.iter_mut() {
-            let Color(r, g, b) = *c;
-            let fr = ((r as f32) / 255.0).powf(gamma);
-            let fg = ((g as f32) / 255.0).powf(gamma);
-            let fb = ((b as f32) / 255.0).powf(gamma);
-            c.0 = (fr * 255.0) as u8;
-            c.1 = (fg * 255.0) as u8;
-            c.2 = (fb * 255.0) as u8;
-        }
-    }
-
     pub fn set_pixel(self: &mut Image, x: i32, y: i32, c: Color) {
         self.data[(x + y * self.width) as usize] = c;
     }
@@ -0,0 +1,62 @@
+use crate::{
+    gbuffer::GBuffer,
+    hdr::HdrImage,
+    la::Vec3f,
+    tga::{Color, Image},
+};
+
+const RAMP_WIDTH: i32 = 256;
+// view space depth jump (for the head, scaled with the framing) and normal angle
+// (as cosine) that count as an edge
+const DEPTH_EDGE: f32 = 0.05;
+const NORMAL_EDGE: f32 = 0.7;
+
+// 1 pixel high ramp with evenly spaced flat bands, the first one is full shadow
+pub fn ramp(bands: usize) -> Image {
+    let bands = bands.max(2);
+    let mut img = Image::new(RAMP_WIDTH, 1);
+    for x in 0..RAMP_WIDTH {
+        let band = (x as usize * bands / RAMP_WIDTH as usize).min(bands - 1);
+        let c = Color::from_linear(&Vec3f(1.0, 1.0, 1.0).mulf(band as f32 / (bands - 1) as f32));
+        img.set_pixel(x, 0, c);
+    }
+    img
+}
+
+// linear light for n·l in [0, 1], any ramp image works, only the first row is used
+pub fn lookup(ramp: &Image, n_l: f32) -> Vec3f {
+    let x = (n_l.clamp(0.0, 1.0) * (ramp.width - 1) as f32).round() as i32;
+    ramp.pixel_at(x, 0).to_linear()
+}
+
+fn is_edge(gbuffer: &GBuffer, x: i32, y: i32, xx: i32, yy: i32, scale: f32) -> bool {
+    if gbuffer.is_empty(xx, yy) {
+        // silhouette against the background
+        return xx >= 0 && yy >= 0 && xx < gbuffer.width && yy < gbuffer.height;
+    }
+    let dz = (gbuffer.depth_at(x, y) - gbuffer.depth_at(xx, yy)).abs();
+    let n = gbuffer.normal_at(x, y).mul(&gbuffer.normal_at(xx, yy));
+    dz > DEPTH_EDGE * scale || n < NORMAL_EDGE
+}
+
+// silhouettes and creases from depth and normal discontinuities,
+// only the closer side of a depth edge is drawn so lines stay one pixel wide,
+// scale is the camera's
+pub fn outlines(out: &mut HdrImage, gbuffer: &GBuffer, color: Vec3f, scale: f32) {
+    for y in 0..out.height {
+        for x in 0..out.width {
+            if gbuffer.is_empty(x, y) {
+                continue;
+            }
+            let z = gbuffer.depth_at(x, y);
+            let edge = [(1, 0), (-1, 0), (0, 1), (0, -1)].iter().any(|(dx, dy)| {
+                let (xx, yy) = (x + dx, y + dy);
+                is_edge(gbuffer, x, y, xx, yy, scale)
+                    && (gbuffer.is_empty(xx, yy) || gbuffer.depth_at(xx, yy) <= z)
+            });
+            if edge {
+                out.set_pixel(x, y, color);
+            }
+        }
+    }
+}
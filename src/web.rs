@@ -6,24 +6,81 @@ use yew::services::fetch::{FetchTask, Request, Response, Uri};
 use yew::services::{ConsoleService, FetchService};
 use yew::{html, Component, Html, NodeRef};
 
-use crate::la::{get_look_at, look_at, persp, Matrix, MatrixI, Vec3f};
+use crate::background::Background;
+use crate::camera::Camera;
+use crate::debug::{self, DebugView};
+use crate::env::Environment;
+use crate::fog::Fog;
+use crate::gbuffer::GBuffer;
+use crate::glyphs;
+use crate::hdr::HdrImage;
+use crate::la::{Matrix, MatrixI, Vec3f};
+use crate::light::Light;
 use crate::model::{self, Wavefront};
-use crate::shader::{triangle, BasicShader, LightShader, Shader, ShaderConf};
+use crate::morph::{Morph, MorphTarget};
+use crate::post::PostChain;
+use crate::scene::{self, Instance, InstanceData, Scene, Transform};
+use crate::shader::{
+    triangle, BasicShader, LightShader, RasterState, Shader, ShaderConf, VertexCache, Winding,
+};
+use crate::skin::Skin;
+use crate::ssao::Ssao;
 use crate::tga::Image;
+use crate::toon;
 
 pub enum Msg {
     Texture(Vec<u8>),
     Model(Vec<u8>),
     Normals(Vec<u8>),
     Upd(Vec3f),
+    Frame,
     UpdC(Vec3f, Vec3f),
     Load(ModelType),
     Diff,
     Spec,
     Txt,
     Zbuff,
+    DebugView,
+    NormalGlyphs,
     Norm,
     Occl,
+    Pbr,
+    Ibl,
+    Background,
+    BackgroundImage(Vec<u8>),
+    ToneMap,
+    Exposure(f32),
+    Focus(f32),
+    Aperture(f32),
+    PostToggle(usize),
+    LightToggle(usize),
+    LightIntensity(usize, f32),
+    PostUp(usize),
+    Metallic(f32),
+    Roughness(f32),
+    AoRadius(f32),
+    AoIntensity(f32),
+    AoSamples(usize),
+    Cel,
+    CelBands(usize),
+    Outlines,
+    AddInstance,
+    NeckRig,
+    MorphList(Vec<u8>),
+    MorphTarget(Vec<u8>),
+    MorphClip,
+    MorphWeight(usize, f32),
+    Clip,
+    Time(f32),
+    Crowd,
+    InstanceVisible(usize),
+    PartVisible(String),
+    Cull,
+    FrontFace,
+    TwoSided,
+    FogMode,
+    FogDensity(f32),
+    HeightFog,
     RotationStarted(i32, i32),
     RotationEnded,
     MoveStarted(i32, i32),
@@ -46,10 +103,18 @@ pub struct Model {
     texture: Option<Image>,
     wavefront: Option<Wavefront>,
     normals: Option<Image>,
-    model: Option<model::Model>,
+    scene: Option<Scene>,
+    // morph target objs of the head, kept until the head is ready
+    morphs: Vec<Wavefront>,
+    // drawn with one instanced call, empty when off
+    crowd: Vec<InstanceData>,
     model_type: ModelType,
-    camvec: Vec3f,
-    cam_lookat: Vec3f,
+    env: Environment,
+    background: Background,
+    post: PostChain,
+    // each light can be switched off
+    lights: Vec<(Light, bool)>,
+    camera: Camera,
     rotation_start: Option<(i32, i32, Vec3f)>,
     move_start: Option<(i32, i32, Vec3f)>,
 }
@@ -58,49 +123,79 @@ impl Model {
     fn render(&mut self) {
         let width: i32 = 512;
         let height: i32 = 512;
-        let mut out_texture = Image::new(width, height);
+        let mut out_texture = HdrImage::new(width, height);
         let mut z_buffer = Image::new(width, height);
         let mut light_texture = Image::new(width, height);
+        let mut gbuffer = GBuffer::new(width, height);
 
-        let camvec = &self.camvec;
-        let lookat_m = get_look_at(&camvec.add(&self.cam_lookat), &self.cam_lookat);
+        let lookat_m = self.camera.look_at_m();
         let lookat_mi = lookat_m.inverse().transpose();
-        let light_dir: Vec3f = persp(5.0, &look_at(&lookat_m, &Vec3f(1.0, -0.0, 0.5))).normalize();
+        // the settings are for the head, scaled to whatever the camera framed
+        let conf = self.conf.framed(&self.camera);
+        let lights: Vec<Light> = self
+            .lights
+            .iter()
+            .filter(|(_, on)| *on)
+            .map(|(l, _)| l.framed(&self.camera).look_at(&lookat_m))
+            .collect();
 
-        let model = self.model.as_ref().unwrap();
+        let scene = self.scene.as_ref().unwrap();
+        let identity = Transform::new().matrix();
+        let ramp = toon::ramp(conf.cel_bands);
+        let env_view = self.env.look_at(&lookat_m);
         let mut shader = BasicShader {
-            conf: self.conf.clone(),
+            conf: conf.clone(),
             normal_face_vec: None,
-            light_dir,
+            lights,
+            env: if conf.ibl { Some(env_view) } else { None },
+            camera: self.camera,
             lookat_m,
             lookat_mi,
-            model,
+            instance: InstanceData::new(&identity),
+            instances: &[],
+            model: &scene.models[0],
+            material: &scene.models[0].material,
+            ramp: &ramp,
             out_texture: &mut out_texture,
             z_buffer: &mut z_buffer,
             varying_uv: Matrix::zeroed(),
             varying_xy: Matrix::zeroed(),
+            varying_view: Matrix::zeroed(),
+            varying_skin: [None; 3],
+            varying_normal: Matrix::zeroed(),
+            varying_normal_delta: Matrix::zeroed(),
+            varying_color: Matrix::zeroed(),
             vertices: [Vec3f::zeroed(); 3],
             light_texture: &mut light_texture,
+            gbuffer: &mut gbuffer,
+            cache: VertexCache::new(),
+            joint_matrices: Vec::new(),
+            joint_normals: Vec::new(),
+            morph_weights: Vec::new(),
+            face: 0,
         };
 
-        for f in 0..model.num_faces() {
-            let mut vertices = [Vec3f::zeroed(), Vec3f::zeroed(), Vec3f::zeroed()];
-            for v in 0..3 {
-                vertices[v] = shader.vertex(f, v);
-            }
-            triangle(&vertices[0], &vertices[1], &vertices[2], &mut shader);
+        scene.draw(&mut shader);
+        scene::draw_instanced(&scene.models[0], &self.crowd, &mut shader);
+
+        // debug views show what the shader wrote as is
+        let shaded = conf.debug == DebugView::Off;
+
+        if shaded {
+            self.background
+                .draw_sky(&mut out_texture, &gbuffer, &env_view);
         }
 
         let light_model = model::Model::screen_texture_model();
 
-        if self.conf.occlusion {
+        if shaded && conf.occlusion {
+            let ambient = Ssao::new(&conf).occlusion(&gbuffer);
             let mut occl_texture = Image::new(width, height);
             let mut light_shader = LightShader {
-                conf: ShaderConf::new(),
+                conf: conf.clone(),
                 model: &light_model,
                 out_texture: &mut out_texture,
-                light_texture: &mut light_texture,
-                z_buffer: &mut z_buffer,
+                ambient: &ambient,
                 varying_uv: Matrix::zeroed(),
                 varying_xy: Matrix::zeroed(),
                 occl_texture: &mut occl_texture,
@@ -115,7 +210,37 @@ impl Model {
             }
         }
 
-        out_texture.apply_gamma(1.5);
+        if shaded && conf.outlines {
+            toon::outlines(
+                &mut out_texture,
+                &gbuffer,
+                Vec3f::zeroed(),
+                self.camera.scale(),
+            );
+        }
+
+        if shaded && conf.fog.enabled() && !conf.fog.per_fragment {
+            conf.fog.apply_pass(&mut out_texture, &gbuffer, &lookat_m);
+        }
+
+        if shaded && conf.lens.enabled() {
+            conf.lens.apply(&mut out_texture, &gbuffer);
+            conf.lens.focus_check(&mut z_buffer, &gbuffer);
+        }
+
+        let mut out_texture = if shaded {
+            let mut out_texture = self.post.run(out_texture, &conf);
+            self.background.draw(&mut out_texture, &gbuffer);
+            out_texture
+        } else {
+            debug::finish(out_texture, &gbuffer, conf.debug)
+        };
+
+        if conf.normal_glyphs {
+            for (model, _, model_m) in scene.visible() {
+                glyphs::draw(&mut out_texture, &gbuffer, model, &model_m, &self.camera);
+            }
+        }
 
         let canvas = self.node_ref.cast::<HtmlCanvasElement>().unwrap();
         let ctx: CanvasRenderingContext2d = canvas
@@ -130,15 +255,33 @@ impl Model {
     }
 
     fn prepare(&mut self) {
-        self.model = Some(model::Model::new(
+        let mut model = model::Model::new(
             self.wavefront.take().unwrap(),
             self.normals.take().unwrap(),
             self.texture.take().unwrap(),
-        ));
+        );
+        self.scene = Some(Scene::single(model));
+        self.attach_morphs();
+    }
+
+    // morph targets that don't match the model are dropped
+    fn attach_morphs(&mut self) {
+        if let Some(scene) = self.scene.as_mut() {
+            let model = &mut scene.models[0];
+            let targets: Vec<MorphTarget> = self
+                .morphs
+                .iter()
+                .filter_map(|t| MorphTarget::from_wavefront(&model.model, &model.mesh, t))
+                .collect();
+            if !targets.is_empty() {
+                scene.instances[0].weights = vec![0.0; targets.len()];
+                model.morph = Some(Morph::cycle(targets));
+            }
+        }
     }
 
     fn ready(&self) -> bool {
-        self.model.is_some()
+        self.scene.is_some()
             || (self.texture.is_some() && self.wavefront.is_some() && self.normals.is_some())
     }
 
@@ -182,10 +325,15 @@ impl Component for Model {
             texture: None,
             wavefront: None,
             normals: None,
-            model: None,
+            scene: None,
+            morphs: vec![],
+            crowd: vec![],
             model_type: ModelType::AFRICAN,
-            camvec: Vec3f(0.5, 0.5, 1.0),
-            cam_lookat: Vec3f(0.0, 0.0, 0.0),
+            env: Environment::sky(),
+            background: Background::Solid(Vec3f::zeroed()),
+            post: PostChain::new(),
+            lights: Light::studio().into_iter().map(|l| (l, true)).collect(),
+            camera: Camera::new(),
             rotation_start: None,
             move_start: None,
         }
@@ -196,6 +344,7 @@ impl Component for Model {
             self.load_binary("./african_head/texture.tga".to_owned(), Msg::Texture);
             self.load_binary("./african_head/normals.tga".to_owned(), Msg::Normals);
             self.load_binary("./african_head/model.obj".to_owned(), Msg::Model);
+            self.load_binary("./african_head/morphs/list.txt".to_owned(), Msg::MorphList);
         }
     }
 
@@ -208,6 +357,26 @@ impl Component for Model {
                 }
                 true
             }
+            Msg::DebugView => {
+                self.conf = ShaderConf {
+                    debug: self.conf.debug.next(),
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::NormalGlyphs => {
+                self.conf = ShaderConf {
+                    normal_glyphs: !self.conf.normal_glyphs,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
             Msg::Occl => {
                 self.conf = ShaderConf {
                     occlusion: !self.conf.occlusion,
@@ -218,6 +387,402 @@ impl Component for Model {
                 }
                 true
             }
+            Msg::AoRadius(r) => {
+                self.conf = ShaderConf {
+                    ssao_radius: r.max(0.01),
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::AoIntensity(i) => {
+                self.conf = ShaderConf {
+                    ssao_intensity: i.max(0.0),
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::AoSamples(n) => {
+                self.conf = ShaderConf {
+                    ssao_samples: n.max(1),
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Cel => {
+                self.conf = ShaderConf {
+                    cel: !self.conf.cel,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::CelBands(n) => {
+                self.conf = ShaderConf {
+                    cel_bands: n.clamp(2, 16),
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Cull => {
+                self.conf = ShaderConf {
+                    raster: RasterState {
+                        cull: self.conf.raster.cull.next(),
+                        ..self.conf.raster
+                    },
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::FrontFace => {
+                self.conf = ShaderConf {
+                    raster: RasterState {
+                        front_face: match self.conf.raster.front_face {
+                            Winding::Ccw => Winding::Cw,
+                            Winding::Cw => Winding::Ccw,
+                        },
+                        ..self.conf.raster
+                    },
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::TwoSided => {
+                self.conf = ShaderConf {
+                    raster: RasterState {
+                        two_sided: !self.conf.raster.two_sided,
+                        ..self.conf.raster
+                    },
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::AddInstance => {
+                if let Some(scene) = self.scene.as_mut() {
+                    // smaller copies on alternating sides of the first one, placed under it
+                    let n = scene.instances.len();
+                    let side = if n % 2 == 1 { 1.0 } else { -1.0 };
+                    scene.instances.push(Instance {
+                        parent: Some(0),
+                        transform: Transform {
+                            translation: Vec3f(side * 0.8 * ((n + 1) / 2) as f32, -0.4, -0.5),
+                            rotation: Vec3f(0.0, -side * 0.5, 0.0),
+                            scale: Vec3f(0.5, 0.5, 0.5),
+                        },
+                        ..Instance::new(0)
+                    });
+                    self.render();
+                }
+                true
+            }
+            Msg::Crowd => {
+                self.crowd = if self.crowd.is_empty() {
+                    scene::crowd(10)
+                } else {
+                    vec![]
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::NeckRig => {
+                // the neck rig only fits the head
+                if let (Some(scene), ModelType::AFRICAN) = (self.scene.as_mut(), &self.model_type) {
+                    let model = &mut scene.models[0];
+                    model.skin = match model.skin {
+                        Some(_) => None,
+                        None => Some(Skin::nod(&model.mesh)),
+                    };
+                    scene.instances[0].clip = None;
+                    self.render();
+                }
+                true
+            }
+            Msg::MorphList(v) => {
+                // file names of the morph targets, one per line
+                for name in String::from_utf8_lossy(&v).lines().map(str::trim) {
+                    if !name.is_empty() {
+                        self.load_binary(
+                            format!("./african_head/morphs/{}", name),
+                            Msg::MorphTarget,
+                        );
+                    }
+                }
+                false
+            }
+            Msg::MorphTarget(v) => {
+                self.morphs.push(Wavefront::parse_string(
+                    String::from_utf8_lossy(&v).to_string(),
+                ));
+                if self.scene.is_some() {
+                    self.attach_morphs();
+                    self.render();
+                }
+                true
+            }
+            Msg::MorphClip => {
+                if let Some(scene) = self.scene.as_mut() {
+                    let instance = &mut scene.instances[0];
+                    instance.morph_clip = match instance.morph_clip {
+                        None if scene.models[0].morph.is_some() => Some(0),
+                        _ => None,
+                    };
+                    self.render();
+                }
+                true
+            }
+            Msg::MorphWeight(i, w) => {
+                if let Some(weight) = self
+                    .scene
+                    .as_mut()
+                    .and_then(|s| s.instances[0].weights.get_mut(i))
+                {
+                    *weight = w.clamp(0.0, 1.0);
+                    self.render();
+                }
+                true
+            }
+            Msg::Clip => {
+                if let Some(scene) = self.scene.as_mut() {
+                    let clips = scene.models[0].skin.as_ref().map_or(0, |s| s.clips.len());
+                    let instance = &mut scene.instances[0];
+                    instance.clip = match instance.clip {
+                        None if clips > 0 => Some(0),
+                        Some(c) if c + 1 < clips => Some(c + 1),
+                        _ => None,
+                    };
+                    self.render();
+                }
+                true
+            }
+            Msg::Time(t) => {
+                if let Some(scene) = self.scene.as_mut() {
+                    scene.instances[0].time = t.max(0.0);
+                    self.render();
+                }
+                true
+            }
+            Msg::InstanceVisible(i) => {
+                if let Some(instance) = self.scene.as_mut().and_then(|s| s.instances.get_mut(i)) {
+                    instance.visible = !instance.visible;
+                    self.render();
+                }
+                true
+            }
+            Msg::PartVisible(part) => {
+                if let Some(instance) = self.scene.as_mut().and_then(|s| s.instances.get_mut(0)) {
+                    match instance.hidden.iter().position(|h| *h == part) {
+                        Some(i) => {
+                            instance.hidden.remove(i);
+                        }
+                        None => instance.hidden.push(part),
+                    }
+                    self.render();
+                }
+                true
+            }
+            Msg::Outlines => {
+                self.conf = ShaderConf {
+                    outlines: !self.conf.outlines,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::FogMode => {
+                self.conf = ShaderConf {
+                    fog: Fog {
+                        mode: self.conf.fog.mode.next(),
+                        ..self.conf.fog
+                    },
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::FogDensity(d) => {
+                self.conf = ShaderConf {
+                    fog: Fog {
+                        density: d.max(0.0),
+                        ..self.conf.fog
+                    },
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::HeightFog => {
+                self.conf = ShaderConf {
+                    fog: Fog {
+                        height_density: if self.conf.fog.height_density > 0.0 {
+                            0.0
+                        } else {
+                            0.3
+                        },
+                        ..self.conf.fog
+                    },
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Pbr => {
+                self.conf = ShaderConf {
+                    pbr: !self.conf.pbr,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Ibl => {
+                self.conf = ShaderConf {
+                    ibl: !self.conf.ibl,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Background => {
+                self.background = match self.background {
+                    Background::Solid(_) => Background::Gradient {
+                        top: Vec3f(0.3, 0.35, 0.45),
+                        bottom: Vec3f(0.02, 0.02, 0.03),
+                    },
+                    Background::Gradient { .. } => Background::Environment,
+                    Background::Environment => {
+                        // the skybox stays until the image arrives
+                        self.load_binary("./background.tga".to_owned(), Msg::BackgroundImage);
+                        Background::Environment
+                    }
+                    Background::Image(_) => Background::Solid(Vec3f::zeroed()),
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::BackgroundImage(v) => {
+                self.background = Background::Image(Image::from_raw_vec(v));
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::ToneMap => {
+                self.conf = ShaderConf {
+                    tone_map: self.conf.tone_map.next(),
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Exposure(e) => {
+                self.conf = ShaderConf {
+                    exposure: e,
+                    ..self.conf
+                };
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Focus(f) => {
+                self.conf.lens.focus_distance = f.max(0.1);
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Aperture(a) => {
+                self.conf.lens.aperture = a.max(0.0);
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::PostToggle(i) => {
+                self.post.toggle(i);
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::LightToggle(i) => {
+                if let Some(l) = self.lights.get_mut(i) {
+                    l.1 = !l.1;
+                }
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::LightIntensity(i, intensity) => {
+                if let Some(l) = self.lights.get_mut(i) {
+                    l.0.set_intensity(intensity);
+                }
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::PostUp(i) => {
+                self.post.move_up(i);
+                if self.ready() {
+                    self.render();
+                }
+                true
+            }
+            Msg::Metallic(m) => {
+                if let Some(scene) = self.scene.as_mut() {
+                    scene.models[0].material.metallic = m.clamp(0.0, 1.0);
+                    self.render();
+                }
+                true
+            }
+            Msg::Roughness(r) => {
+                if let Some(scene) = self.scene.as_mut() {
+                    scene.models[0].material.roughness = r.clamp(0.0, 1.0);
+                    self.render();
+                }
+                true
+            }
             Msg::Norm => {
                 self.conf = ShaderConf {
                     normals: !self.conf.normals,
@@ -259,12 +824,19 @@ impl Component for Model {
                 true
             }
             Msg::Upd(v) => {
-                self.camvec = v;
+                self.camera.dir = v;
                 if self.ready() {
                     self.render();
                 }
                 true
             }
+            Msg::Frame => {
+                if let Some(scene) = self.scene.as_ref() {
+                    self.camera = self.camera.frame(scene.bounding_sphere_with(&self.crowd));
+                    self.render();
+                }
+                true
+            }
             Msg::Texture(v) => {
                 self.texture = Some(Image::from_raw_vec(v));
                 if self.ready() {
@@ -290,7 +862,7 @@ impl Component for Model {
                 true
             }
             Msg::RotationStarted(x, y) => {
-                self.rotation_start = Some((x, y, self.camvec));
+                self.rotation_start = Some((x, y, self.camera.dir));
                 true
             }
             Msg::Noop => false,
@@ -299,7 +871,7 @@ impl Component for Model {
                 true
             }
             Msg::MoveStarted(x, y) => {
-                self.move_start = Some((x, y, self.cam_lookat));
+                self.move_start = Some((x, y, self.camera.target));
                 true
             }
             Msg::MoveEnded => {
@@ -308,15 +880,15 @@ impl Component for Model {
             }
             Msg::UpdC(Vec3f(dx, dy, _), old_place) => {
                 ConsoleService::log(format!("{:?}, {:?}", dx, dy).as_str());
-                let camvec = Vec3f(self.camvec.0, 0.0, self.camvec.2)
+                let camvec = Vec3f(self.camera.dir.0, 0.0, self.camera.dir.2)
                     .normalize()
                     .mulf(dy / 500.0);
                 let perp: Vec3f = Vec3f(0.0, 1.0, 0.0)
-                    .cross(&self.camvec)
+                    .cross(&self.camera.dir)
                     .normalize()
                     .mulf(dx / 500.0);
 
-                self.cam_lookat = old_place.add(&perp).add(&camvec);
+                self.camera.target = old_place.add(&perp).add(&camvec);
 
                 if self.ready() {
                     self.render();
@@ -328,7 +900,8 @@ impl Component for Model {
                     ModelType::AFRICAN => {
                         if let ModelType::AFRICAN = self.model_type {
                         } else {
-                            self.model = None;
+                            self.scene = None;
+                            self.morphs.clear();
                             self.texture = None;
                             self.normals = None;
                             self.wavefront = None;
@@ -342,12 +915,17 @@ impl Component for Model {
                             self.load_binary("./african_head/model.obj".to_owned(), |v| {
                                 Msg::Model(v)
                             });
+                            self.load_binary(
+                                "./african_head/morphs/list.txt".to_owned(),
+                                Msg::MorphList,
+                            );
                         }
                     }
                     ModelType::DIABLO => {
                         if let ModelType::DIABLO = self.model_type {
                         } else {
-                            self.model = None;
+                            self.scene = None;
+                            self.morphs.clear();
                             self.texture = None;
                             self.normals = None;
                             self.wavefront = None;
@@ -368,7 +946,56 @@ impl Component for Model {
     }
 
     fn view(&self) -> Html {
-        let Vec3f(x, y, z) = self.camvec;
+        let Vec3f(x, y, z) = self.camera.dir;
+        let ao_radius = self.conf.ssao_radius;
+        let ao_intensity = self.conf.ssao_intensity;
+        let ao_samples = self.conf.ssao_samples;
+        let exposure = self.conf.exposure;
+        let fog_density = self.conf.fog.density;
+        let cel_bands = self.conf.cel_bands;
+        let focus = self.conf.lens.focus_distance;
+        let aperture = self.conf.lens.aperture;
+        let height_fog = self.conf.fog.height_density > 0.0;
+        let (metallic, roughness) = self
+            .scene
+            .as_ref()
+            .map(|s| {
+                (
+                    s.models[0].material.metallic,
+                    s.models[0].material.roughness,
+                )
+            })
+            .unwrap_or((0.0, 0.0));
+        let (clip, time) = self
+            .scene
+            .as_ref()
+            .map(|s| {
+                let instance = &s.instances[0];
+                let clip = s.models[0]
+                    .skin
+                    .as_ref()
+                    .zip(instance.clip)
+                    .and_then(|(skin, c)| skin.clips.get(c))
+                    .map_or("Off", |c| c.name.as_str());
+                (clip, instance.time)
+            })
+            .unwrap_or(("Off", 0.0));
+        let neck_rig = self
+            .scene
+            .as_ref()
+            .map_or(false, |s| s.models[0].skin.is_some());
+        // target weights are only used without the clip
+        let (morph_clip, morph_weights) = self
+            .scene
+            .as_ref()
+            .filter(|s| s.models[0].morph.is_some())
+            .map_or((None, vec![]), |s| {
+                let instance = &s.instances[0];
+                (
+                    Some(instance.morph_clip.is_some()),
+                    instance.weights.clone(),
+                )
+            });
         let pos = self.rotation_start;
         let place = self.move_start;
         html! {
@@ -427,13 +1054,132 @@ impl Component for Model {
                                 { "z: " }{ format!("{:.2}", z) }
                                 <button onclick=self.link.callback(move |_| Msg::Upd(Vec3f(x, y, z-0.1)))>{ "-" }</button>
                             </div>
+                            <button onclick=self.link.callback(move |_| Msg::Frame)>{ "Frame model" }</button>
                             <button class=if self.conf.diff_light { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Diff)>{ "Diffuse light" }</button>
                             <button class=if self.conf.spec_light { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Spec)>{ "Specular light" }</button>
                             <button class=if self.conf.texture { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Txt)>{ "Texture" }</button>
                             <button class=if self.conf.normals { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Norm)>{ "Normal map" }</button>
                             <button class=if self.conf.occlusion { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Occl)>{ "Ambient occlusion" }</button>
+                            { if self.conf.occlusion { html! {
+                                <>
+                                    <div class="button-row">
+                                        <button onclick=self.link.callback(move |_| Msg::AoRadius(ao_radius + 0.02))>{ "+" }</button>
+                                        { "radius: " }{ format!("{:.2}", ao_radius) }
+                                        <button onclick=self.link.callback(move |_| Msg::AoRadius(ao_radius - 0.02))>{ "-" }</button>
+                                    </div>
+                                    <div class="button-row">
+                                        <button onclick=self.link.callback(move |_| Msg::AoIntensity(ao_intensity + 0.25))>{ "+" }</button>
+                                        { "power: " }{ format!("{:.2}", ao_intensity) }
+                                        <button onclick=self.link.callback(move |_| Msg::AoIntensity(ao_intensity - 0.25))>{ "-" }</button>
+                                    </div>
+                                    <div class="button-row">
+                                        <button onclick=self.link.callback(move |_| Msg::AoSamples(ao_samples * 2))>{ "+" }</button>
+                                        { "samples: " }{ ao_samples }
+                                        <button onclick=self.link.callback(move |_| Msg::AoSamples(ao_samples / 2))>{ "-" }</button>
+                                    </div>
+                                </>
+                            } } else { html! {} } }
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Cull)>{ format!("Cull: {}", self.conf.raster.cull.name()) }</button>
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::FrontFace)>{ if let Winding::Ccw = self.conf.raster.front_face { "Front face: CCW" } else { "Front face: CW" } }</button>
+                            <button class=if self.conf.raster.two_sided { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::TwoSided)>{ "Two-sided lighting" }</button>
+                            <button class=if self.conf.cel { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Cel)>{ "Cel shading" }</button>
+                            { if self.conf.cel { html! {
+                                <div class="button-row">
+                                    <button onclick=self.link.callback(move |_| Msg::CelBands(cel_bands + 1))>{ "+" }</button>
+                                    { "bands: " }{ cel_bands }
+                                    <button onclick=self.link.callback(move |_| Msg::CelBands(cel_bands - 1))>{ "-" }</button>
+                                </div>
+                            } } else { html! {} } }
+                            <button class=if self.conf.outlines { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Outlines)>{ "Outlines" }</button>
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::FogMode)>{ format!("Fog: {}", self.conf.fog.mode.name()) }</button>
+                            <div class="button-row">
+                                <button onclick=self.link.callback(move |_| Msg::FogDensity(fog_density + 0.05))>{ "+" }</button>
+                                { "fog density: " }{ format!("{:.2}", fog_density) }
+                                <button onclick=self.link.callback(move |_| Msg::FogDensity(fog_density - 0.05))>{ "-" }</button>
+                            </div>
+                            <button class=if height_fog { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::HeightFog)>{ "Height fog" }</button>
+                            <button class=if self.conf.pbr { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Pbr)>{ "PBR" }</button>
+                            { if self.conf.pbr { html! {
+                                <>
+                                    <div class="button-row">
+                                        <button onclick=self.link.callback(move |_| Msg::Metallic(metallic + 0.1))>{ "+" }</button>
+                                        { "metal: " }{ format!("{:.1}", metallic) }
+                                        <button onclick=self.link.callback(move |_| Msg::Metallic(metallic - 0.1))>{ "-" }</button>
+                                    </div>
+                                    <div class="button-row">
+                                        <button onclick=self.link.callback(move |_| Msg::Roughness(roughness + 0.1))>{ "+" }</button>
+                                        { "rough: " }{ format!("{:.1}", roughness) }
+                                        <button onclick=self.link.callback(move |_| Msg::Roughness(roughness - 0.1))>{ "-" }</button>
+                                    </div>
+                                </>
+                            } } else { html! {} } }
+                            <button class=if self.conf.ibl { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Ibl)>{ "Environment light" }</button>
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::Background)>{ format!("Background: {}", self.background.name()) }</button>
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::ToneMap)>{ format!("Tone map: {}", self.conf.tone_map.name()) }</button>
+                            <div class="button-row">
+                                <button onclick=self.link.callback(move |_| Msg::Exposure(exposure + 0.5))>{ "+" }</button>
+                                { "exp: " }{ format!("{:.1}", exposure) }
+                                <button onclick=self.link.callback(move |_| Msg::Exposure(exposure - 0.5))>{ "-" }</button>
+                            </div>
+                            <div class="button-row">
+                                <button onclick=self.link.callback(move |_| Msg::Focus(focus + 0.1))>{ "+" }</button>
+                                { "focus: " }{ format!("{:.1}", focus) }
+                                <button onclick=self.link.callback(move |_| Msg::Focus(focus - 0.1))>{ "-" }</button>
+                            </div>
+                            <div class="button-row">
+                                <button onclick=self.link.callback(move |_| Msg::Aperture(aperture + 0.005))>{ "+" }</button>
+                                { "aperture: " }{ format!("{:.3}", aperture) }
+                                <button onclick=self.link.callback(move |_| Msg::Aperture(aperture - 0.005))>{ "-" }</button>
+                            </div>
+                            { for self.post.passes.iter().enumerate().map(|(i, (pass, on))| html! {
+                                <div class="button-row">
+                                    <button class=if *on { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::PostToggle(i))>{ pass.name() }</button>
+                                    <button disabled={ self.zbuff || i == 0 } onclick=self.link.callback(move |_| Msg::PostUp(i))>{ "^" }</button>
+                                </div>
+                            }) }
+                            { for self.lights.iter().enumerate().map(|(i, (light, on))| {
+                                let intensity = light.intensity();
+                                html! {
+                                    <div class="button-row">
+                                        <button class=if *on { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::LightToggle(i))>{ light.name() }</button>
+                                        <button onclick=self.link.callback(move |_| Msg::LightIntensity(i, intensity + 0.1))>{ "+" }</button>
+                                        { format!("{:.1}", intensity) }
+                                        <button onclick=self.link.callback(move |_| Msg::LightIntensity(i, intensity - 0.1))>{ "-" }</button>
+                                    </div>
+                                }
+                            }) }
                             <button onclick=self.link.callback(move |_| Msg::Zbuff)>{ "Z Buffer" }</button>
+                            <button disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::DebugView)>{ format!("Debug view: {}", self.conf.debug.name()) }</button>
+                            <button class=if self.conf.normal_glyphs { "" } else { "off" } disabled={ self.zbuff } onclick=self.link.callback(move |_| Msg::NormalGlyphs)>{ "Normal glyphs" }</button>
                             <div style="height: 100px"></div>
+                            { for self.scene.iter().flat_map(|s| s.instances.iter().enumerate()).map(|(i, instance)| html! {
+                                <button class=if instance.visible { "" } else { "off" } onclick=self.link.callback(move |_| Msg::InstanceVisible(i))>{ format!("Instance {}", i) }</button>
+                            }) }
+                            { for self.scene.iter().flat_map(|s| s.models[0].model.parts().into_iter().map(move |p| (p.to_string(), s.instances[0].hidden.iter().any(|h| h == p)))).map(|(part, hidden)| {
+                                let name = part.clone();
+                                html! {
+                                    <button class=if hidden { "off" } else { "" } onclick=self.link.callback(move |_| Msg::PartVisible(name.clone()))>{ part }</button>
+                                }
+                            }) }
+                            <button onclick=self.link.callback(move |_| Msg::AddInstance)>{ "Add instance" }</button>
+                            <button class=if neck_rig { "" } else { "off" } disabled={ !matches!(self.model_type, ModelType::AFRICAN) } onclick=self.link.callback(move |_| Msg::NeckRig)>{ "Neck rig" }</button>
+                            <button onclick=self.link.callback(move |_| Msg::Clip)>{ format!("Animation: {}", clip) }</button>
+                            <div class="button-row">
+                                <button onclick=self.link.callback(move |_| Msg::Time(time + 0.1))>{ "+" }</button>
+                                { "time: " }{ format!("{:.1}", time) }
+                                <button onclick=self.link.callback(move |_| Msg::Time(time - 0.1))>{ "-" }</button>
+                            </div>
+                            { for morph_clip.iter().map(|on| html! {
+                                <button class=if *on { "" } else { "off" } onclick=self.link.callback(move |_| Msg::MorphClip)>{ "Morph cycle" }</button>
+                            }) }
+                            { for morph_weights.iter().copied().enumerate().map(|(i, w)| html! {
+                                <div class="button-row">
+                                    <button disabled={ morph_clip == Some(true) } onclick=self.link.callback(move |_| Msg::MorphWeight(i, w + 0.1))>{ "+" }</button>
+                                    { format!("target {}: {:.1}", i, w) }
+                                    <button disabled={ morph_clip == Some(true) } onclick=self.link.callback(move |_| Msg::MorphWeight(i, w - 0.1))>{ "-" }</button>
+                                </div>
+                            }) }
+                            <button class=if self.crowd.is_empty() { "off" } else { "" } onclick=self.link.callback(move |_| Msg::Crowd)>{ "Crowd" }</button>
                             <button class=if let ModelType::AFRICAN=self.model_type { "off" } else { "" } onclick=self.link.callback(move |_| Msg::Load(ModelType::AFRICAN))>{ "African head" }</button>
                             <button class=if let ModelType::DIABLO=self.model_type { "off" } else { "" } onclick=self.link.callback(move |_| Msg::Load(ModelType::DIABLO))>{ "Diablo" }</button>
                         </>